@@ -0,0 +1,164 @@
+use crate::{error::Fallible, iter::index_bounds, Error, Event, EventFile, EventRef};
+use futures_core::Stream;
+use std::{
+    ops::RangeBounds,
+    panic,
+    pin::Pin,
+    sync::{Arc, Mutex, MutexGuard},
+    task::{Context, Poll},
+    thread,
+    time::Duration,
+};
+use tokio::{sync::mpsc, task::spawn_blocking};
+
+/// Number of events an [`EventStream`] reads ahead of its consumer.
+const STREAM_BUFFER: usize = 256;
+/// Interval at which a live stream checks whether it has been dropped while waiting for new events.
+const TAIL_CHECK: Duration = Duration::from_millis(100);
+
+/// Handle for using an [`EventFile`] from within a tokio runtime, available with the `tokio` feature.
+///
+/// All file access — including compression and decompression — happens on tokio’s blocking thread pool. The
+/// handle can be cloned and shared between tasks, appends are serialised.
+#[derive(Clone)]
+pub struct AsyncEventFile {
+    file: Arc<Mutex<EventFile>>,
+}
+
+impl AsyncEventFile {
+    pub fn new(file: EventFile) -> Self {
+        Self { file: Arc::new(Mutex::new(file)) }
+    }
+
+    async fn with<T: Send + 'static>(
+        &self, f: impl FnOnce(&mut EventFile) -> Fallible<T> + Send + 'static,
+    ) -> Fallible<T> {
+        let file = self.file.clone();
+        blocking(move || f(&mut *lock(&file)?)).await
+    }
+
+    /// Append an event, returning the index assigned to it, see [`EventFile::append`].
+    pub async fn append(&self, event: impl AsRef<[u8]> + Send + 'static) -> Fallible<u64> {
+        self.with(move |file| file.append(event.as_ref())).await
+    }
+
+    /// Append an event if it would be assigned the `expected` index, see [`EventFile::append_if`].
+    pub async fn append_if(&self, expected: u64, event: impl AsRef<[u8]> + Send + 'static) -> Fallible<u64> {
+        self.with(move |file| file.append_if(expected, event.as_ref())).await
+    }
+
+    pub async fn flush(&self) -> Fallible<()> {
+        self.with(|file| file.flush()).await
+    }
+
+    /// Index that will be assigned to the next appended event.
+    pub async fn next_index(&self) -> Fallible<u64> {
+        self.with(|file| file.next_index()).await
+    }
+
+    /// Retrieve a single event by its index, see [`EventFile::get`].
+    pub async fn get(&self, idx: u64) -> Fallible<Option<EventRef>> {
+        self.with(move |file| file.get(idx)).await
+    }
+
+    /// Stream the events in the given range that are stored at the time of the call.
+    ///
+    /// The events are read through a separate mapping of the file, so appending is not held up by the stream.
+    /// Must be called from within a tokio runtime.
+    pub fn events(&self, range: impl RangeBounds<u64>) -> EventStream {
+        let (start, end) = index_bounds(range);
+        let file = self.file.clone();
+        let (stream, feed) = EventStream::new(move |tx| {
+            let (mut sub, end) = {
+                let file = lock(&file)?;
+                (file.subscribe(start)?, end.min(file.next_index()?.saturating_sub(1)))
+            };
+            while sub.next_index() <= end {
+                let Some(event) = sub.next_timeout(Duration::ZERO)? else {
+                    break;
+                };
+                if tx.blocking_send(Ok(event)).is_err() {
+                    break;
+                }
+            }
+            Ok(())
+        });
+        spawn_blocking(feed);
+        stream
+    }
+
+    /// Stream all events starting at index `from_idx`, including those appended in the future.
+    ///
+    /// The stream is fed by a thread of its own rather than from tokio’s blocking thread pool, which it would
+    /// otherwise occupy for as long as it waits for new events. It ends once all handles to this file have been
+    /// dropped.
+    pub fn subscribe(&self, from_idx: u64) -> EventStream {
+        let file = self.file.clone();
+        let (stream, feed) = EventStream::new(move |tx| {
+            let mut sub = lock(&file)?.subscribe(from_idx)?;
+            drop(file);
+            while !tx.is_closed() {
+                let Some(event) = sub.next_timeout(TAIL_CHECK)? else {
+                    if sub.is_closed() {
+                        break;
+                    }
+                    continue;
+                };
+                if tx.blocking_send(Ok(event)).is_err() {
+                    break;
+                }
+            }
+            Ok(())
+        });
+        thread::spawn(feed);
+        stream
+    }
+}
+
+/// A stream of events, see [`AsyncEventFile::events`] and [`AsyncEventFile::subscribe`].
+///
+/// The first error is returned as the last item.
+pub struct EventStream {
+    rx: mpsc::Receiver<Fallible<Event>>,
+}
+
+impl EventStream {
+    /// A stream of the events sent by `f`, together with the function feeding it, which is to be run on a thread
+    /// outside the async runtime.
+    fn new(
+        f: impl FnOnce(&mpsc::Sender<Fallible<Event>>) -> Fallible<()> + Send + 'static,
+    ) -> (Self, impl FnOnce() + Send + 'static) {
+        let (tx, rx) = mpsc::channel(STREAM_BUFFER);
+        let feed = move || {
+            if let Err(e) = f(&tx) {
+                let _ = tx.blocking_send(Err(e));
+            }
+        };
+        (Self { rx }, feed)
+    }
+}
+
+impl Stream for EventStream {
+    type Item = Fallible<Event>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.rx.poll_recv(cx)
+    }
+}
+
+/// Lock the file, which is no longer usable if an operation panicked while holding the lock, as it may have left
+/// the file half-modified.
+fn lock(file: &Mutex<EventFile>) -> Fallible<MutexGuard<'_, EventFile>> {
+    file.lock().map_err(|_| Error::poisoned())
+}
+
+/// Run `f` on the blocking thread pool, propagating its panics.
+async fn blocking<T: Send + 'static>(f: impl FnOnce() -> Fallible<T> + Send + 'static) -> Fallible<T> {
+    match spawn_blocking(f).await {
+        Ok(res) => res,
+        Err(e) => match e.try_into_panic() {
+            Ok(payload) => panic::resume_unwind(payload),
+            Err(_) => Err(Error::cancelled()),
+        },
+    }
+}
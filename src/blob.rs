@@ -0,0 +1,115 @@
+//! Events too large for the staging area, see [`EventFileConfig::blob_threshold`](crate::EventFileConfig::blob_threshold).
+//!
+//! Each such event is compressed on its own into a block of level [`BLOB_LEVEL`] that is not part of the index.
+//! In its place the staging area (and later the leaf) holds a reference to the blob, marked by [`JUMP_BLOB`] in
+//! the jump table: the blob’s stream offset followed by the event hash, so that Merkle trees can be built without
+//! reading the blob.
+
+use crate::{
+    codec,
+    crypt::decrypt,
+    error::Fallible,
+    formats::{BlobHeader, BlockHeader, HasMagic, BLOB_LEVEL},
+    merkle::{self, Digest},
+    store::Store,
+    u32_to_usize, usize_to_u64, Error,
+};
+use std::sync::{Arc, PoisonError};
+
+/// Length of a blob reference: stream offset and event hash.
+pub(crate) const BLOB_REF_LEN: usize = 40;
+
+pub(crate) fn blob_ref(offset: u64, hash: &Digest) -> [u8; BLOB_REF_LEN] {
+    let mut ret = [0; BLOB_REF_LEN];
+    ret[..8].copy_from_slice(&offset.to_be_bytes());
+    ret[8..].copy_from_slice(hash.as_bytes());
+    ret
+}
+
+/// Stream offset and event hash from a blob reference.
+pub(crate) fn parse_ref(reference: &[u8]) -> Fallible<(u64, Digest)> {
+    let malformed = || {
+        Error::data_corruption(
+            "malformed blob reference",
+            usize_to_u64(reference.len()),
+            usize_to_u64(BLOB_REF_LEN),
+        )
+    };
+    let (offset, hash) = reference.split_first_chunk::<8>().ok_or_else(malformed)?;
+    let hash = <[u8; 32]>::try_from(hash).map_err(|_| malformed())?;
+    Ok((u64::from_be_bytes(*offset), hash.into()))
+}
+
+impl Store {
+    /// The contents of the blob holding event `idx`, given the reference stored in its place.
+    pub fn blob(&self, reference: &[u8], idx: u64) -> Fallible<Arc<[u8]>> {
+        let (offset, _) = parse_ref(reference)?;
+        let key = (self.id, offset);
+        if let Some(bytes) = self.cache.lock().unwrap_or_else(PoisonError::into_inner).get(key) {
+            return Ok(bytes);
+        }
+        let bytes = self.load_blob(reference, idx)?;
+        self.cache.lock().unwrap_or_else(PoisonError::into_inner).put(key, bytes.clone(), false);
+        Ok(bytes)
+    }
+
+    /// Read the blob like [`blob`](Self::blob) but bypassing the cache, checking it against the event hash in the
+    /// reference, which is what the leaf commits to.
+    pub(crate) fn load_blob(&self, reference: &[u8], idx: u64) -> Fallible<Arc<[u8]>> {
+        let (offset, hash) = parse_ref(reference)?;
+        if offset < self.file.start_offset() {
+            return Err(Error::data_not_present(
+                "blob has been dropped",
+                offset,
+                self.file.start_offset(),
+            ));
+        }
+        self.file.verify_block(offset)?;
+        let block: &BlockHeader = self.file.stream_at(offset)?;
+        let header: &BlobHeader = self.file.stream_after(block)?;
+        if block.level() != BLOB_LEVEL || header.idx() != idx {
+            return Err(Error::data_corruption("blob of another event", header.idx(), idx));
+        }
+        let bytes =
+            self.file.stream_bytes_after(header, u32_to_usize(block.length()).saturating_sub(BlobHeader::LEN))?;
+        let plain;
+        let bytes = match block.key_id() {
+            0 => bytes,
+            key_id => {
+                plain = decrypt(&self.key(key_id)?, header.as_slice(), bytes)?;
+                &plain[..]
+            }
+        };
+        let bytes = Arc::<[u8]>::from(codec::decompress(&self.codec, header.codec(), bytes, None)?);
+        if bytes.len() != u32_to_usize(header.len()) {
+            return Err(Error::data_corruption(
+                "blob length",
+                usize_to_u64(bytes.len()),
+                u64::from(header.len()),
+            ));
+        }
+        if merkle::event_hash(&bytes) != hash {
+            return Err(Error::data_corruption("blob does not match its event hash", offset, idx));
+        }
+        Ok(bytes)
+    }
+
+    /// Offset of the first blob written after the last leaf before `offset`, or `offset` if there is none.
+    ///
+    /// Blobs are written while their events are staged, so those from here on may belong to events at or after
+    /// `offset`.
+    pub(crate) fn blobs_start(&self, offset: u64) -> Fallible<u64> {
+        let mut found = None;
+        let mut pos = self.file.start_offset();
+        while pos < offset {
+            let block: &BlockHeader = self.file.stream_at(pos)?;
+            match block.level() {
+                0 => found = None,
+                BLOB_LEVEL => found = found.or(Some(pos)),
+                _ => {}
+            }
+            pos += BlockHeader::SIZE + ((u64::from(block.length()) + 7) & !7);
+        }
+        Ok(found.unwrap_or(offset))
+    }
+}
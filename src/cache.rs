@@ -1,6 +1,9 @@
 use std::sync::{Arc, Mutex};
 
-pub trait Cache {
+/// Cache for decompressed blocks, keyed by file id and block offset.
+///
+/// Caches must be `Send` so that event files and their readers can be handed to other threads.
+pub trait Cache: Send {
     fn put(&mut self, key: (u32, u64), value: Arc<[u8]>, prio: bool);
     fn get(&mut self, key: (u32, u64)) -> Option<Arc<[u8]>>;
 }
@@ -42,23 +45,45 @@ mod pl {
     }
 }
 
+#[cfg(feature = "fbr")]
+pub use fbr::SendFbrCache;
+
 #[cfg(feature = "fbr")]
 mod fbr {
     use super::Cache;
     use fbr_cache::FbrCache;
     use std::sync::Arc;
 
-    impl<const C: usize> Cache for FbrCache<(u32, u64), Arc<[u8]>, C> {
+    /// An [`FbrCache`] of decompressed blocks that can be used as a [`Cache`].
+    ///
+    /// `FbrCache` is not `Send` since its entries are linked through raw pointers.
+    pub struct SendFbrCache<const C: usize>(FbrCache<(u32, u64), Arc<[u8]>, C>);
+
+    // SAFETY: the cache exclusively owns all entries its internal pointers refer to and never hands them out, so
+    // moving it to another thread as a whole is sound; keys and values are `Send`.
+    unsafe impl<const C: usize> Send for SendFbrCache<C> {}
+
+    impl<const C: usize> SendFbrCache<C> {
+        pub fn new(cache: FbrCache<(u32, u64), Arc<[u8]>, C>) -> Self {
+            Self(cache)
+        }
+
+        pub fn into_inner(self) -> FbrCache<(u32, u64), Arc<[u8]>, C> {
+            self.0
+        }
+    }
+
+    impl<const C: usize> Cache for SendFbrCache<C> {
         fn put(&mut self, key: (u32, u64), value: Arc<[u8]>, prio: bool) {
             if prio {
-                self.put_prio(key, value);
+                self.0.put_prio(key, value);
             } else {
-                self.put(key, value);
+                self.0.put(key, value);
             }
         }
 
         fn get(&mut self, key: (u32, u64)) -> Option<Arc<[u8]>> {
-            self.get(&key).cloned()
+            self.0.get(&key).cloned()
         }
     }
 }
@@ -0,0 +1,146 @@
+use crate::{
+    error::{ErrCtx, Fallible},
+    Error,
+};
+use std::{
+    io::{self, Read, Write},
+    sync::Arc,
+};
+
+/// Compression applied to the contents of leaf blocks, see [`EventFileConfig::codec`](crate::EventFileConfig::codec).
+///
+/// The codec’s id is stored with each block, so that files written with different codecs over time can still be
+/// read. Ids below 256 are reserved for the codecs provided by this crate; a custom codec needs to be configured
+/// for reading blocks written with it.
+///
+/// Codecs may make use of the dictionary stored in the file, see
+/// [`EventFileConfig::dictionary_training`](crate::EventFileConfig::dictionary_training); by default it is ignored.
+pub trait Codec: Send + Sync {
+    fn id(&self) -> u32;
+    fn compress(&self, data: &[u8]) -> Fallible<Vec<u8>>;
+    fn decompress(&self, data: &[u8]) -> Fallible<Vec<u8>>;
+
+    /// Whether this codec makes use of dictionaries; if not, blocks are compressed without one.
+    fn supports_dictionary(&self) -> bool {
+        false
+    }
+
+    fn compress_with_dictionary(&self, data: &[u8], _dictionary: &[u8]) -> Fallible<Vec<u8>> {
+        self.compress(data)
+    }
+
+    fn decompress_with_dictionary(&self, data: &[u8], _dictionary: &[u8]) -> Fallible<Vec<u8>> {
+        self.decompress(data)
+    }
+}
+
+/// Store the data uncompressed.
+pub struct NoCompression;
+
+impl NoCompression {
+    pub const ID: u32 = 0;
+}
+
+impl Codec for NoCompression {
+    fn id(&self) -> u32 {
+        Self::ID
+    }
+
+    fn compress(&self, data: &[u8]) -> Fallible<Vec<u8>> {
+        Ok(data.to_vec())
+    }
+
+    fn decompress(&self, data: &[u8]) -> Fallible<Vec<u8>> {
+        Ok(data.to_vec())
+    }
+}
+
+/// Zstandard compression at the given level (1–22); high levels compress well but slowly.
+pub struct Zstd {
+    pub level: i32,
+}
+
+impl Zstd {
+    pub const ID: u32 = 1;
+}
+
+impl Default for Zstd {
+    fn default() -> Self {
+        Self { level: 21 }
+    }
+}
+
+impl Codec for Zstd {
+    fn id(&self) -> u32 {
+        Self::ID
+    }
+
+    fn compress(&self, data: &[u8]) -> Fallible<Vec<u8>> {
+        Ok(zstd::encode_all(data, self.level).ctx("compressing")?)
+    }
+
+    fn decompress(&self, data: &[u8]) -> Fallible<Vec<u8>> {
+        Ok(zstd::decode_all(data).ctx("decompressing leaf")?)
+    }
+
+    fn supports_dictionary(&self) -> bool {
+        true
+    }
+
+    fn compress_with_dictionary(&self, data: &[u8], dictionary: &[u8]) -> Fallible<Vec<u8>> {
+        let mut encoder = zstd::Encoder::with_dictionary(Vec::new(), self.level, dictionary).ctx("compressing")?;
+        encoder.write_all(data).ctx("compressing")?;
+        Ok(encoder.finish().ctx("compressing")?)
+    }
+
+    fn decompress_with_dictionary(&self, data: &[u8], dictionary: &[u8]) -> Fallible<Vec<u8>> {
+        let mut decoder = zstd::Decoder::with_dictionary(data, dictionary).ctx("decompressing leaf")?;
+        let mut bytes = Vec::new();
+        decoder.read_to_end(&mut bytes).ctx("decompressing leaf")?;
+        Ok(bytes)
+    }
+}
+
+/// LZ4 compression, which is fast but less thorough.
+pub struct Lz4;
+
+impl Lz4 {
+    pub const ID: u32 = 2;
+}
+
+impl Codec for Lz4 {
+    fn id(&self) -> u32 {
+        Self::ID
+    }
+
+    fn compress(&self, data: &[u8]) -> Fallible<Vec<u8>> {
+        Ok(lz4_flex::compress_prepend_size(data))
+    }
+
+    fn decompress(&self, data: &[u8]) -> Fallible<Vec<u8>> {
+        let res = lz4_flex::decompress_size_prepended(data).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e));
+        Ok(res.ctx("decompressing leaf")?)
+    }
+}
+
+/// Decompress data written with the codec of the given id, using the configured codec for custom ids.
+pub(crate) fn decompress(
+    configured: &Arc<dyn Codec>, id: u32, data: &[u8], dictionary: Option<&[u8]>,
+) -> Fallible<Vec<u8>> {
+    let codec: &dyn Codec = match id {
+        NoCompression::ID => &NoCompression,
+        Zstd::ID => &Zstd { level: 21 },
+        Lz4::ID => &Lz4,
+        id if id == configured.id() => &**configured,
+        id => return Err(Error::unknown_codec(id)),
+    };
+    match dictionary {
+        Some(dictionary) => codec.decompress_with_dictionary(data, dictionary),
+        None => codec.decompress(data),
+    }
+}
+
+/// Train a zstd dictionary of at most `max_size` bytes on the given events.
+pub(crate) fn train_dictionary(samples: &[Vec<u8>], max_size: usize) -> Fallible<Vec<u8>> {
+    Ok(zstd::dict::from_samples(samples, max_size).ctx("training dictionary")?)
+}
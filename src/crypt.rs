@@ -0,0 +1,51 @@
+//! Encryption at rest, see [`EventFileConfig::key_provider`](crate::EventFileConfig::key_provider).
+//!
+//! Compressed leaves and staged events are sealed with XChaCha20-Poly1305 under a random nonce, which is stored
+//! in front of the ciphertext. The associated data bind the ciphertext to its place in the file: the leaf header
+//! for a leaf, the event index for a staged event.
+
+use crate::{error::Fallible, Error};
+use chacha20poly1305::{
+    aead::{Aead, AeadCore, KeyInit, OsRng, Payload},
+    Key, XChaCha20Poly1305, XNonce,
+};
+
+const NONCE_LEN: usize = 24;
+
+/// Number of bytes by which [`encrypt`] lengthens its input: the nonce and the authentication tag.
+pub(crate) const OVERHEAD: usize = NONCE_LEN + 16;
+
+/// Source of the keys for encrypting event data at rest.
+///
+/// The id of the key is recorded with the encrypted data, so keys can be rotated by switching the current key
+/// while still providing the old ones for reading. Id 0 denotes unencrypted data and must not be used.
+pub trait KeyProvider: Send + Sync {
+    /// Id of the key with which newly written data shall be encrypted.
+    fn current_key_id(&self) -> u32;
+
+    /// The 256-bit key with the given id, or `None` if it is not available.
+    fn key(&self, key_id: u32) -> Option<[u8; 32]>;
+}
+
+/// Encrypt `plain`, returning the nonce followed by the ciphertext.
+pub(crate) fn encrypt(key: &[u8; 32], aad: &[u8], plain: &[u8]) -> Fallible<Vec<u8>> {
+    let cipher = XChaCha20Poly1305::new(Key::from_slice(key));
+    let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let sealed = cipher.encrypt(&nonce, Payload { msg: plain, aad }).map_err(|_| Error::crypto("encrypting"))?;
+    let mut ret = Vec::with_capacity(NONCE_LEN + sealed.len());
+    ret.extend_from_slice(&nonce);
+    ret.extend_from_slice(&sealed);
+    Ok(ret)
+}
+
+/// Decrypt the output of [`encrypt`], failing if the key, the associated data, or the ciphertext is wrong.
+pub(crate) fn decrypt(key: &[u8; 32], aad: &[u8], sealed: &[u8]) -> Fallible<Vec<u8>> {
+    if sealed.len() < NONCE_LEN {
+        return Err(Error::crypto("ciphertext too short"));
+    }
+    let (nonce, msg) = sealed.split_at(NONCE_LEN);
+    let cipher = XChaCha20Poly1305::new(Key::from_slice(key));
+    cipher
+        .decrypt(XNonce::from_slice(nonce), Payload { msg, aad })
+        .map_err(|_| Error::crypto("decryption failed (wrong key or corrupted data)"))
+}
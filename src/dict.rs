@@ -0,0 +1,91 @@
+//! Compression dictionaries shared by all leaves, see
+//! [`EventFileConfig::dictionary_training`](crate::EventFileConfig::dictionary_training).
+//!
+//! Dictionaries are stored in blocks of level [`DICT_LEVEL`] that are not part of the index: each points to its
+//! predecessor via `prev_block` and the file header points to the latest one. Every leaf records the id of the
+//! dictionary it was compressed with, so that older dictionaries are kept as long as leaves refer to them.
+
+use crate::{
+    crypt::decrypt,
+    error::Fallible,
+    formats::{BlockHeader, DictHeader, HasMagic, LeafHeader, DICT_LEVEL},
+    store::Store,
+    u32_to_usize, Error,
+};
+use std::sync::{Arc, PoisonError};
+
+/// Id and (decrypted) contents of a dictionary.
+pub(crate) type LoadedDict = (u32, Arc<[u8]>);
+
+impl Store {
+    /// Stream offsets and ids of all retained dictionaries, newest first.
+    pub fn dictionary_blocks(&self) -> Fallible<Vec<(u64, u32)>> {
+        let mut ret = Vec::new();
+        let mut offset = self.file.dict_offset();
+        while offset != u64::MAX && offset >= self.file.start_offset() {
+            let block: &BlockHeader = self.file.stream_at(offset)?;
+            if block.level() != DICT_LEVEL {
+                return Err(Error::data_corruption("dictionary block expected", u64::from(block.level()), 0));
+            }
+            let header: &DictHeader = self.file.stream_after(block)?;
+            ret.push((offset, header.dict_id()));
+            offset = block.prev_block();
+        }
+        Ok(ret)
+    }
+
+    /// Id of the dictionary that new blocks are compressed with, 0 if there is none.
+    pub fn dictionary_id(&self) -> Fallible<u32> {
+        Ok(self.dictionary_blocks()?.first().map_or(0, |(_, id)| *id))
+    }
+
+    /// The (decrypted) contents of the dictionary with the given id.
+    pub fn dictionary(&self, dict_id: u32) -> Fallible<Arc<[u8]>> {
+        if let Some((id, bytes)) = &*self.dict.lock().unwrap_or_else(PoisonError::into_inner) {
+            if *id == dict_id {
+                return Ok(bytes.clone());
+            }
+        }
+        let offset = self.dictionary_blocks()?.into_iter().find(|(_, id)| *id == dict_id).map(|(offset, _)| offset);
+        let offset = offset.ok_or(Error::data_corruption("dictionary not found", u64::from(dict_id), 0))?;
+        self.file.verify_block(offset)?;
+        let block: &BlockHeader = self.file.stream_at(offset)?;
+        let header: &DictHeader = self.file.stream_after(block)?;
+        let bytes =
+            self.file.stream_bytes_after(header, u32_to_usize(block.length()).saturating_sub(DictHeader::LEN))?;
+        let bytes = Arc::<[u8]>::from(match block.key_id() {
+            0 => bytes.to_vec(),
+            key_id => decrypt(&self.key(key_id)?, header.as_slice(), bytes)?,
+        });
+        *self.dict.lock().unwrap_or_else(PoisonError::into_inner) = Some((dict_id, bytes.clone()));
+        Ok(bytes)
+    }
+
+    /// Offsets of the dictionaries to be copied to the stream end before dropping the blocks before `before`,
+    /// oldest first; empty if no dictionary that is still needed would be dropped.
+    pub fn dictionaries_to_relocate(&self, before: u64) -> Fallible<Vec<u64>> {
+        let blocks = self.dictionary_blocks()?;
+        let Some(&(_, latest)) = blocks.first() else {
+            return Ok(Vec::new());
+        };
+        // the latest dictionary is needed for new blocks, older ones only while retained leaves refer to them
+        let mut needed = latest;
+        let end = self.file.end_offset();
+        let mut offset = before;
+        while offset < end {
+            let block: &BlockHeader = self.file.stream_at(offset)?;
+            if block.level() == 0 {
+                let leaf: &LeafHeader = self.file.stream_after(block)?;
+                if leaf.dict() != 0 {
+                    needed = needed.min(leaf.dict());
+                }
+            }
+            offset += BlockHeader::SIZE + ((u64::from(block.length()) + 7) & !7);
+        }
+        let keep = blocks.into_iter().filter(|(_, id)| *id >= needed).collect::<Vec<_>>();
+        if keep.iter().all(|(offset, _)| *offset >= before) {
+            return Ok(Vec::new());
+        }
+        Ok(keep.into_iter().rev().map(|(offset, _)| offset).collect())
+    }
+}
@@ -1,6 +1,13 @@
 use crate::{
-    formats::{BlockHeader, BranchHeader, HasMagic, IndexEntry, LeafHeader, StagingHeader},
-    u32_to_usize, usize_to_u64, Error, EventFile,
+    formats::{
+        BlobHeader, BlockHeader, BranchHeader, DictHeader, EventCheck, EventTime, HasMagic, IndexEntry, JumpEntry,
+        LeafHeader, StagingHeader, BLOB_LEVEL, DICT_LEVEL, LEAF_SIGNED,
+    },
+    iter::{decode_leaf, leaf_parts, Layout, LeafParts},
+    merkle::block_digest,
+    mmap::Staging,
+    store::{StagingLayout, Store},
+    u32_to_usize, usize_to_u64, Error,
 };
 use std::io;
 
@@ -16,48 +23,102 @@ macro_rules! err {
     };
 }
 
-impl EventFile {
+impl Store {
     pub fn dump_text(&self, lines_per_event: usize, mut w: impl io::Write) -> io::Result<()> {
         let file = &self.file;
         let head = err!(file.header(), w).lift();
         writeln!(
             w,
-            "header: stream={} user={} start={} end={}",
-            head.stream_version, head.user_version, head.start_offset, head.end_offset
+            "header: stream={} user={} start={} end={} base={} dict={} staging={} sealed={}",
+            head.stream_version,
+            head.user_version,
+            head.start_offset,
+            head.end_offset,
+            head.base_offset,
+            head.dict_offset,
+            head.staging_offset,
+            head.sealed_offset
         )?;
         let mut offset = head.start_offset;
         while offset < head.end_offset {
             let block = err!(file.stream_at::<BlockHeader>(offset), w);
             writeln!(
                 w,
-                "block @ {}: prev={} level={} length={}",
+                "block @ {}: prev={} level={} length={} checksum={:#010x} key={} digest={}",
                 offset,
                 block.prev_block(),
                 block.level(),
-                block.length()
+                block.length(),
+                block.checksum(),
+                block.key_id(),
+                block_digest(block)
             )?;
+            let next = offset + BlockHeader::SIZE + ((u64::from(block.length()) + 7) & !7);
+            if let Err(e) = file.verify_block(offset) {
+                writeln!(w, "  error: {}", e)?;
+                offset = next;
+                continue;
+            }
+            if block.level() == DICT_LEVEL {
+                let dict: &DictHeader = err!(file.stream_after(block), w);
+                writeln!(w, "  dictionary: id={} samples={}", dict.dict_id(), dict.samples())?;
+                offset = next;
+                continue;
+            }
+            if block.level() == BLOB_LEVEL {
+                let blob: &BlobHeader = err!(file.stream_after(block), w);
+                writeln!(w, "  blob: idx={} len={} codec={}", blob.idx(), blob.len(), blob.codec())?;
+                offset = next;
+                continue;
+            }
             if block.level() == 0 {
                 let leaf: &LeafHeader = err!(file.stream_after(block), w);
-                writeln!(w, "  leaf: start={} count={}", leaf.start_idx(), leaf.count())?;
-                let bytes = u32_to_usize(block.length()) - LeafHeader::LEN;
-                let compressed = err!(file.stream_bytes_after(leaf, bytes), w);
-                let decomp = err!(zstd::decode_all(compressed), w);
-                for i in 0..=leaf.count() {
-                    if i & 15 == 0 {
-                        if i > 0 {
-                            writeln!(w)?;
+                writeln!(
+                    w,
+                    "  leaf: start={} count={} codec={} dict={} sealed={} time={}..={}{}",
+                    leaf.start_idx(),
+                    leaf.count(),
+                    leaf.codec(),
+                    leaf.dict(),
+                    leaf.sealed(),
+                    leaf.min_time(),
+                    leaf.max_time(),
+                    if leaf.flags() & LEAF_SIGNED != 0 { " signed" } else { "" }
+                )?;
+                let LeafParts { times, .. } = err!(leaf_parts(file, block), w);
+                let decomp = err!(decode_leaf(self, block), w);
+                let layout = Layout::of_leaf(leaf, &decomp);
+                match layout {
+                    Layout::Jump(_) => {
+                        for i in 0..=leaf.count() {
+                            if i & 15 == 0 {
+                                if i > 0 {
+                                    writeln!(w)?;
+                                }
+                                write!(w, "   ")?;
+                            }
+                            let off = get_u32_as_usize(&decomp, i);
+                            write!(w, " {}", off)?;
                         }
-                        write!(w, "   ")?;
+                        writeln!(w)?;
                     }
-                    let off = get_u32_as_usize(&*decomp, i);
-                    write!(w, " {}", off)?;
+                    Layout::Fixed(size) => writeln!(w, "    fixed size={}", size)?,
                 }
-                writeln!(w)?;
-                let base = u32_to_usize(leaf.count() + 1) * 4;
                 for i in 0..leaf.count() {
-                    writeln!(w, "    event {}:", i)?;
-                    let from = base + get_u32_as_usize(&*decomp, i);
-                    let to = base + get_u32_as_usize(&*decomp, i + 1);
+                    let (from, to, marker) = match layout {
+                        Layout::Jump(base) => (
+                            base + get_u32_as_usize(&decomp, i),
+                            base + get_u32_as_usize(&decomp, i + 1),
+                            blob_marker(&decomp, i),
+                        ),
+                        Layout::Fixed(size) => (u32_to_usize(i) * size, u32_to_usize(i + 1) * size, ""),
+                    };
+                    match times.get(u32_to_usize(i) * EventTime::LEN..) {
+                        Some(time) if !time.is_empty() => {
+                            writeln!(w, "    event {}: time={}{}", i, EventTime::from_slice(time).time(), marker)?
+                        }
+                        _ => writeln!(w, "    event {}:{}", i, marker)?,
+                    }
                     let event = err!(
                         decomp.get(from..to).ok_or_else(|| Error::data_corruption(
                             "event past end",
@@ -72,7 +133,14 @@ impl EventFile {
                 }
             } else {
                 let branch: &BranchHeader = err!(file.stream_after(block), w);
-                writeln!(w, "  branch: prev={}, end={}", branch.prev_offset(), branch.end_idx())?;
+                writeln!(
+                    w,
+                    "  branch: prev={}, end={}, time={}..={}",
+                    branch.prev_offset(),
+                    branch.end_idx(),
+                    branch.min_time(),
+                    branch.max_time()
+                )?;
                 let entries = (u32_to_usize(block.length()) - BranchHeader::LEN) / IndexEntry::LEN;
                 let mut prev = None;
                 for i in 0..entries {
@@ -80,54 +148,95 @@ impl EventFile {
                         Some(p) => err!(file.stream_after(p), w),
                         None => err!(file.stream_after(branch), w),
                     };
-                    writeln!(w, "    {:2}: offset={} start={}", i, idx.offset(), idx.start_idx())?;
+                    writeln!(
+                        w,
+                        "    {:2}: offset={} start={} time={}..={}",
+                        i,
+                        idx.offset(),
+                        idx.start_idx(),
+                        idx.min_time(),
+                        idx.max_time()
+                    )?;
                     prev = Some(idx);
                 }
             }
-            offset += BlockHeader::SIZE + ((u64::from(block.length()) + 7) & !7);
+            offset = next;
         }
         writeln!(w, "---")?;
-        let staging = err!(file.staging_at::<StagingHeader>(0), w);
-        writeln!(
-            w,
-            "staging: last_block={} start={} count={} capacity={}",
-            staging.last_block(),
-            staging.start_idx(),
-            staging.count(),
-            staging.capacity()
-        )?;
-        let idx_bytes_end = StagingHeader::LEN + u32_to_usize(4 * staging.capacity());
-        let idx_bytes = err!(file.staging_bytes(StagingHeader::LEN, idx_bytes_end), w);
-        for i in 0..staging.capacity() {
-            let offset = get_u32_as_usize(idx_bytes, i);
-            if offset != 0 {
-                writeln!(w, "  {:4}: {}", i, offset)?;
+        let sealed = file.sealed_offset().map(|_| ("sealed", Staging::Sealed));
+        for (name, area) in sealed.into_iter().chain([("staging", Staging::Active)]) {
+            let staging = err!(file.area_at::<StagingHeader>(area, 0), w);
+            writeln!(
+                w,
+                "{}: last_block={} start={} count={} capacity={} key={} last_hash={}",
+                name,
+                staging.last_block(),
+                staging.start_idx(),
+                staging.count(),
+                staging.capacity(),
+                staging.key_id(),
+                staging.last_hash()
+            )?;
+            let layout = StagingLayout::of(file, &staging.lift());
+            match layout.fixed {
+                Some(size) => writeln!(w, "  fixed size={}", size)?,
+                None => {
+                    let idx_bytes = err!(file.area_bytes(area, layout.jump_idx(0), layout.check_idx(0)), w);
+                    for i in 0..staging.capacity() {
+                        let offset = get_u32_as_usize(idx_bytes, i);
+                        if offset != 0 {
+                            writeln!(w, "  {:4}: {}", i, offset)?;
+                        }
+                    }
+                }
             }
-        }
-        let event_bytes = err!(file.staging_bytes(idx_bytes_end, file.staging_len()), w);
-        for i in 0..staging.count() {
-            writeln!(w, "  event {}:", i)?;
-            let from = get_u32_as_usize(idx_bytes, i);
-            let to = get_u32_as_usize(idx_bytes, i + 1);
-            let event = err!(
-                event_bytes.get(from..to).ok_or_else(|| Error::data_corruption(
-                    "event past end",
-                    usize_to_u64(to),
-                    usize_to_u64(event_bytes.len()),
-                )),
-                w
-            );
-            for line in 0..lines_per_event {
-                hex_dump(event, line, &mut w)?;
+            let event_bytes = err!(file.area_bytes(area, layout.event_start(), err!(file.area_len(area), w)), w);
+            for i in 0..staging.count() {
+                let pos = u32_to_usize(i);
+                let check = err!(file.area_at::<EventCheck>(area, layout.check_idx(pos)), w);
+                let time = err!(file.area_at::<EventTime>(area, layout.time_idx(pos)), w);
+                let (from, to, blob) = err!(layout.event_range(file, area, pos), w);
+                writeln!(
+                    w,
+                    "  event {}: crc={:#010x} end={} time={}{}",
+                    i,
+                    check.crc(),
+                    check.end(),
+                    time.time(),
+                    if blob { " blob" } else { "" }
+                )?;
+                let event = err!(
+                    event_bytes.get(from..to).ok_or_else(|| Error::data_corruption(
+                        "event past end",
+                        usize_to_u64(to),
+                        usize_to_u64(event_bytes.len()),
+                    )),
+                    w
+                );
+                for line in 0..lines_per_event {
+                    hex_dump(event, line, &mut w)?;
+                }
             }
         }
         Ok(())
     }
 }
 
-fn get_u32_as_usize(bytes: &[u8], idx: u32) -> usize {
+fn jump_entry(bytes: &[u8], idx: u32) -> &JumpEntry {
     let pos = u32_to_usize(4 * idx);
-    u32_to_usize(u32::from_be_bytes(bytes[pos..pos + 4].try_into().unwrap()))
+    JumpEntry::from_slice(&bytes[pos..pos + JumpEntry::LEN])
+}
+
+fn get_u32_as_usize(bytes: &[u8], idx: u32) -> usize {
+    u32_to_usize(jump_entry(bytes, idx).offset())
+}
+
+fn blob_marker(jump_table: &[u8], idx: u32) -> &'static str {
+    if jump_entry(jump_table, idx + 1).is_blob() {
+        " blob"
+    } else {
+        ""
+    }
 }
 
 fn hex_dump(bytes: &[u8], line: usize, w: &mut impl io::Write) -> io::Result<()> {
@@ -21,6 +21,26 @@ pub enum Error {
     DataCorruption { message: &'static str, found: u64, expected: u64 },
     #[error("data not present: {message} (desired at {offset}, boundary at {boundary})")]
     DataNotPresent { message: &'static str, offset: u64, boundary: u64 },
+    #[error("checksum mismatch in block at offset {offset}: expected {expected:#010x} found {found:#010x}")]
+    ChecksumMismatch { offset: u64, found: u32, expected: u32 },
+    #[error("invalid signature in block at offset {offset}: {message}")]
+    InvalidSignature { message: &'static str, offset: u64 },
+    #[error("no key with id {0} available for decryption")]
+    UnknownKey(u32),
+    #[error("encryption error: {0}")]
+    Crypto(&'static str),
+    #[error("no codec with id {0} available for decompression")]
+    UnknownCodec(u32),
+    #[error("event of {found} bytes does not match the fixed event size of {expected} bytes")]
+    WrongEventSize { expected: u32, found: usize },
+    #[error("batch of {size} events exceeds the staging area’s limit of {limit} events")]
+    BatchTooLarge { size: usize, limit: u32 },
+    #[error("file is opened read-only")]
+    ReadOnly,
+    #[error("background task was cancelled")]
+    Cancelled,
+    #[error("file handle is unusable after a panic during an earlier operation")]
+    Poisoned,
     #[error("attempt to write beyond end of file")]
     WriteBeyondEnd,
 }
@@ -44,6 +64,36 @@ impl Error {
     pub const fn data_not_present(message: &'static str, offset: u64, boundary: u64) -> Self {
         Self::DataNotPresent { message, offset, boundary }
     }
+    pub const fn checksum_mismatch(offset: u64, found: u32, expected: u32) -> Self {
+        Self::ChecksumMismatch { offset, found, expected }
+    }
+    pub const fn invalid_signature(message: &'static str, offset: u64) -> Self {
+        Self::InvalidSignature { message, offset }
+    }
+    pub const fn unknown_key(key_id: u32) -> Self {
+        Self::UnknownKey(key_id)
+    }
+    pub const fn crypto(message: &'static str) -> Self {
+        Self::Crypto(message)
+    }
+    pub const fn unknown_codec(codec: u32) -> Self {
+        Self::UnknownCodec(codec)
+    }
+    pub const fn wrong_event_size(expected: u32, found: usize) -> Self {
+        Self::WrongEventSize { expected, found }
+    }
+    pub const fn batch_too_large(size: usize, limit: u32) -> Self {
+        Self::BatchTooLarge { size, limit }
+    }
+    pub const fn read_only() -> Self {
+        Self::ReadOnly
+    }
+    pub const fn cancelled() -> Self {
+        Self::Cancelled
+    }
+    pub const fn poisoned() -> Self {
+        Self::Poisoned
+    }
     pub const fn write_beyond_end() -> Self {
         Self::WriteBeyondEnd
     }
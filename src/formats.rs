@@ -1,6 +1,6 @@
 #![allow(unused)]
 
-use crate::u32_to_usize;
+use crate::{merkle::Digest, u32_to_usize};
 use core::{
     mem::{align_of, size_of},
     slice::from_raw_parts,
@@ -26,6 +26,7 @@ macro_rules! decl {
             }
             #[cfg(not(feature = "native"))]
             impl $name {
+                #[allow(clippy::too_many_arguments)]
                 pub fn new($($field:$tpe,)+) -> Self {
                     Self { $($field: $field.to_be(),)+ }
                 }
@@ -43,6 +44,7 @@ macro_rules! decl {
             }
             #[cfg(feature = "native")]
             impl $name {
+                #[allow(clippy::too_many_arguments)]
                 pub fn new($($field:$tpe,)+) -> Self {
                     Self { $($field,)+ }
                 }
@@ -131,57 +133,184 @@ decl! {
         /// user-defined version for stream payload data format
         user_version: u32,
         /// offset of the first stored byte relative to stream start
-        start_offset: u64,
+        start_offset / set_start_offset: u64,
         /// offset of the first byte beyond the stored stream
         end_offset / set_end_offset: u64,
-    } = (24, 8, b"Events01");
+        /// stream offset of the byte stored right after the file header (bytes from here up to `start_offset`
+        /// have been dropped)
+        base_offset: u64,
+        /// stream offset of the latest dictionary block (-1 for None)
+        dict_offset / set_dict_offset: u64,
+        /// stream offset of the active staging area (at or after `end_offset`)
+        staging_offset / set_staging_offset: u64,
+        /// stream offset of the sealed staging area awaiting compression (-1 for None)
+        sealed_offset / set_sealed_offset: u64,
+        /// size of every event if the file was created with a fixed event size (0 for None)
+        event_size: u32,
+        /// number of events in every leaf if the file was created with a fixed event size
+        leaf_events: u32,
+    } = (64, 8, b"Events01");
 
     struct BlockHeader / BlockHeaderLifted {
         /// stream offset of immediately preceding block (-1 for None)
         prev_block: u64,
-        /// level or this block
+        /// level or this block ([`DICT_LEVEL`] for dictionaries and [`BLOB_LEVEL`] for blobs, which are not part
+        /// of the index)
         level: u32,
         /// length of this block’s payload excluding padding
         length: u32,
-    } = (16, 8, b"BlockSta");
+        /// CRC32 checksum over this block’s payload
+        checksum / set_checksum: u32,
+        /// id of the key the compressed data are encrypted with (0 for none)
+        key_id: u32,
+        /// digest of the immediately preceding block (zero for None)
+        prev_hash: Digest,
+        /// hash over this block’s contents, see [`crate::merkle`]
+        content_hash: Digest,
+    } = (88, 8, b"BlockSta");
 
     struct LeafHeader / LeafHeaderLifted {
         /// index of first event in this block
         start_idx: u64,
+        /// time at which this block was written (milliseconds since the UNIX epoch)
+        sealed: u64,
+        /// smallest event timestamp in this block
+        min_time: u64,
+        /// largest event timestamp in this block
+        max_time: u64,
         /// number of events in this block
         count: u32,
-    } = (16, 8, b"LeafHead");
+        /// combination of `LEAF_*` flags
+        flags: u32,
+        /// id of the [`Codec`](crate::Codec) the events are compressed with
+        codec: u32,
+        /// id of the dictionary the events are compressed with (0 for none)
+        dict: u32,
+    } = (48, 8, b"LeafHead");
 
     struct BranchHeader / BranchHeaderLifted {
         /// offset of the previous index block of level same or higher (-1 for None)
         prev_offset: u64,
         /// exclusive upper bound on event indices in this block
         end_idx: u64,
-    } = (16, 8, b"BranchHd");
+        /// smallest event timestamp below this block
+        min_time: u64,
+        /// largest event timestamp below this block
+        max_time: u64,
+    } = (32, 8, b"BranchHd");
 
     struct IndexEntry / IndexEntryLifted {
         offset: u64,
         start_idx: u64,
-    } = (16, 8, b"");
+        /// smallest event timestamp within the referenced block
+        min_time: u64,
+        /// largest event timestamp within the referenced block
+        max_time: u64,
+        /// digest of the referenced block
+        digest: Digest,
+    } = (64, 8, b"");
 
     struct JumpEntry / JumpEntryLifted {
+        /// offset of the end of an event’s data, with [`JUMP_BLOB`] set if the event is stored in a blob
         pos: u32,
     } = (4, 4, b"");
 
+    struct EventTime / EventTimeLifted {
+        /// caller-defined timestamp, 0 if none was given
+        time: u64,
+    } = (8, 8, b"");
+
+    struct EventCheck / EventCheckLifted {
+        /// CRC32 checksum over the event’s bytes
+        crc: u32,
+        /// staging event count once this event is committed (sequence marker)
+        end: u32,
+    } = (8, 4, b"");
+
+    struct DictHeader / DictHeaderLifted {
+        /// version number of this dictionary, counting from 1
+        dict_id: u32,
+        /// number of events the dictionary was trained on (0 if supplied by the user)
+        samples: u32,
+    } = (8, 4, b"DictHead");
+
+    struct BlobHeader / BlobHeaderLifted {
+        /// index of the event stored in this blob
+        idx: u64,
+        /// length of the event
+        len: u32,
+        /// id of the [`Codec`](crate::Codec) the event is compressed with
+        codec: u32,
+    } = (16, 8, b"BlobHead");
+
     struct StagingHeader / StagingHeaderLifted {
         /// stream offset of the preceding compressed block’s header
-        last_block: u64,
+        last_block / set_last_block: u64,
         /// index number of the first stored event
         start_idx: u64,
         /// number of events stored
         count / set_count: u32,
         /// number of event index slots allocated
         capacity: u32,
-    } = (24, 8, b"Staging!");
+        /// id of the key the staged events are encrypted with (0 for none)
+        key_id: u32,
+        /// digest of the preceding compressed block (zero for None)
+        last_hash / set_last_hash: Digest,
+    } = (64, 8, b"Staging!");
+
+}
+
+/// block level of a dictionary block, whose `prev_block` points to the previous dictionary block
+pub const DICT_LEVEL: u32 = u32::MAX;
+
+/// block level of a blob block, holding a single event that is too large to be stored in a leaf
+pub const BLOB_LEVEL: u32 = u32::MAX - 1;
 
+/// jump entry flag: the event ending here is stored in a blob block, its data only hold a reference, see
+/// [`crate::blob`]
+pub const JUMP_BLOB: u32 = 1 << 31;
+
+/// leaf flag: the [`LeafHeader`] is followed by one [`EventTime`] per event
+pub const LEAF_TIMES: u32 = 1;
+/// leaf flag: the timestamp table is followed by the hash of the preceding leaf and a signature, see
+/// [`crate::sign`]
+pub const LEAF_SIGNED: u32 = 2;
+/// leaf flag: the decompressed events all have the same size and are stored back to back without a jump table
+pub const LEAF_FIXED: u32 = 4;
+
+impl JumpEntry {
+    /// Offset of the end of an event’s data.
+    pub fn offset(&self) -> u32 {
+        self.pos() & !JUMP_BLOB
+    }
+
+    /// Whether the event ending here is stored in a blob block.
+    pub fn is_blob(&self) -> bool {
+        self.pos() & JUMP_BLOB != 0
+    }
+}
+
+impl LeafHeader {
+    /// Length of the timestamp table following this header.
+    pub fn times_len(&self) -> usize {
+        if self.flags() & LEAF_TIMES != 0 {
+            u32_to_usize(self.count()) * EventTime::LEN
+        } else {
+            0
+        }
+    }
+
+    /// Length of the signature trailer following the timestamp table.
+    pub fn signature_len(&self) -> usize {
+        if self.flags() & LEAF_SIGNED != 0 {
+            crate::sign::SIGNATURE_LEN
+        } else {
+            0
+        }
+    }
 }
 
 #[test]
 fn align() {
-    assert_eq!(MmapFileHeader::SIZE, 32);
+    assert_eq!(MmapFileHeader::SIZE, 72);
 }
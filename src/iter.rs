@@ -1,14 +1,18 @@
 use crate::{
-    error::{ErrCtx, Fallible},
-    formats::{BlockHeader, BranchHeader, HasMagic, IndexEntry, JumpEntry, LeafHeader, StagingHeader},
-    mmap::MmapFile,
-    u32_to_usize, Cache, EventFile,
+    codec,
+    crypt::decrypt,
+    error::Fallible,
+    formats::{BlockHeader, BranchHeader, HasMagic, IndexEntry, JumpEntry, LeafHeader, StagingHeader, LEAF_FIXED},
+    mmap::{MmapFile, Staging},
+    read_ahead::ReadAhead,
+    store::{FileLock, StagingLayout, Store, StoreRef},
+    u32_to_usize, usize_to_u64, Error,
 };
 use smallvec::SmallVec;
 use std::{
-    cell::RefCell,
-    ops::{Bound, RangeBounds},
-    sync::Arc,
+    fmt::{self, Debug, Formatter},
+    ops::{Bound, Deref, RangeBounds},
+    sync::{Arc, PoisonError},
 };
 
 macro_rules! handle_err {
@@ -39,7 +43,8 @@ impl<'a> Iterator for SearchIter<'a> {
     type Item = Fallible<(u64, &'a BlockHeader)>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.offset == u64::MAX {
+        if self.offset == u64::MAX || self.offset < self.file.start_offset() {
+            // reached the beginning or blocks that have been dropped
             return None;
         }
         let ret = handle_err!(self.file.stream_at::<BlockHeader>(self.offset), self.offset = u64::MAX);
@@ -58,10 +63,7 @@ impl<'a> Iterator for SearchIter<'a> {
 }
 
 pub struct RangeIter<'a> {
-    file: &'a MmapFile,
-    cache: &'a RefCell<Box<dyn Cache>>,
-    block_event_limit: u32,
-    id: u32,
+    store: StoreRef<'a>,
     done: bool,
     /// next event index to deliver
     start_idx: u64,
@@ -69,38 +71,28 @@ pub struct RangeIter<'a> {
     end_idx: u64,
     /// stack from which matching top-level branches are popped
     todo: SmallVec<[u64; 16]>,
+    /// staged events to be delivered once the compressed blocks are done, last one first
+    staged: SmallVec<[LeafSlice; 2]>,
+    /// leaves being decompressed ahead of the caller, see [`read_ahead`](Self::read_ahead)
+    read_ahead: Option<ReadAhead>,
 }
 
 impl<'a> RangeIter<'a> {
-    pub fn new(file: &'a EventFile, last_block: u64, range: impl RangeBounds<u64>) -> Fallible<Self> {
-        let EventFile { file, cache, id, block_event_limit, .. } = file;
-
-        let (start_idx, end_idx) =
-            if range.start_bound() == Bound::Excluded(&u64::MAX) || range.end_bound() == Bound::Excluded(&0) {
-                (1, 0)
-            } else {
-                let start = match range.start_bound() {
-                    Bound::Included(i) => *i,
-                    Bound::Excluded(e) => *e + 1,
-                    Bound::Unbounded => 0,
-                };
-                let end = match range.end_bound() {
-                    Bound::Included(i) => *i,
-                    Bound::Excluded(e) => *e - 1,
-                    Bound::Unbounded => u64::MAX,
-                };
-                (start, end)
-            };
+    /// Iterate over the range as stored now: the staged events are copied right away, since the writer may have
+    /// compressed them by the time the iterator gets to them.
+    pub(crate) fn new(store: StoreRef<'a>, last_block: u64, range: impl RangeBounds<u64>) -> Fallible<Self> {
+        let file = &store.file;
+
+        let (start_idx, end_idx) = index_bounds(range);
         if start_idx > end_idx {
             return Ok(Self {
-                file,
-                cache,
-                id: *id,
-                block_event_limit: *block_event_limit,
+                store,
                 done: true,
                 start_idx,
                 end_idx,
                 todo: SmallVec::new(),
+                staged: SmallVec::new(),
+                read_ahead: None,
             });
         }
 
@@ -123,39 +115,150 @@ impl<'a> RangeIter<'a> {
                 }
             })
             .collect::<Fallible<_>>()?;
+        let mut staged = SmallVec::new();
+        for area in [Staging::Active, Staging::Sealed] {
+            staged.extend(staging_slice(&store, area, start_idx, end_idx)?);
+        }
 
         Ok(Self {
-            file,
-            cache,
-            id: *id,
-            block_event_limit: *block_event_limit,
+            store,
             done: false,
             start_idx,
             end_idx,
             todo,
+            staged,
+            read_ahead: None,
         })
     }
 
+    /// Decompress the next `leaves` leaves on worker threads ahead of the caller, which speeds up long scans.
+    ///
+    /// The worker threads, one per core, are shared by all iterators. Leaves are still delivered in order; the decompressed leaves pass through the [`Cache`](crate::Cache)
+    /// without being prioritised. To be called before iterating.
+    pub fn read_ahead(self, leaves: usize) -> Self {
+        let read_ahead = (leaves > 0).then(|| ReadAhead::new(&self.store, leaves));
+        Self { read_ahead, ..self }
+    }
+
     fn decompress(&self, header: &BlockHeader, prio: bool) -> Fallible<Arc<[u8]>> {
-        debug_assert!(header.level() == 0);
-        let key = (self.id, self.file.stream_offset(header)?);
-        let bytes = self.cache.borrow_mut().get(key);
-        if let Some(bytes) = bytes {
-            tracing::trace!(?key, "cache hit");
-            Ok(bytes)
-        } else {
-            tracing::trace!(?key, prio, "cache miss");
-            let leaf: &LeafHeader = self.file.stream_after(header)?;
-            let length = u32_to_usize(header.length()) - LeafHeader::LEN;
-            let bytes = self.file.stream_bytes_after(leaf, length)?;
-            let bytes = zstd::decode_all(bytes).ctx("decompressing index")?;
-            let bytes = Arc::<[u8]>::from(bytes);
-            self.cache.borrow_mut().put(key, bytes.clone(), prio);
-            Ok(bytes)
+        decompress(&self.store, header, prio)
+    }
+
+    /// Find the next leaf within the range, returning its offset and the first event index to deliver from it.
+    fn next_leaf(&mut self) -> Fallible<Option<(u64, u64)>> {
+        let Some(mut offset) = self.todo.last().copied() else {
+            return Ok(None);
+        };
+        loop {
+            let block: &BlockHeader = self.store.file.stream_at(offset)?;
+            if block.level() == 0 {
+                if Some(&offset) == self.todo.last() {
+                    self.todo.pop();
+                }
+                let leaf: &LeafHeader = self.store.file.stream_after(block)?;
+                let start_idx = self.start_idx;
+                self.start_idx = leaf.start_idx() + u64::from(leaf.count());
+                return Ok(Some((offset, start_idx)));
+            }
+            let branch: &BranchHeader = self.store.file.stream_after(block)?;
+            if branch.end_idx() <= self.start_idx || self.start_idx > self.end_idx {
+                self.todo.pop();
+                match self.todo.last() {
+                    Some(next) => offset = *next,
+                    None => return Ok(None),
+                }
+                continue;
+            }
+            let count = (u32_to_usize(block.length()) - BranchHeader::LEN) / IndexEntry::LEN;
+            let mut e: &IndexEntry = self.store.file.stream_after(branch)?;
+            for _ in 1..count {
+                let n: &IndexEntry = self.store.file.stream_after(e)?;
+                if n.start_idx() > self.start_idx {
+                    break;
+                }
+                e = n;
+            }
+            offset = e.offset();
+        }
+    }
+
+    /// Submit leaves to the read-ahead workers until enough are in flight.
+    fn fill_read_ahead(&mut self) {
+        let lock = self.store.read_lock();
+        let _guard = lock.as_deref().map(FileLock::read);
+        while self.read_ahead.as_ref().is_some_and(ReadAhead::has_room) {
+            let leaf = self.next_leaf();
+            let Some(read_ahead) = self.read_ahead.as_mut() else {
+                return;
+            };
+            match leaf {
+                Ok(Some((offset, start_idx))) => read_ahead.submit(offset, start_idx),
+                Ok(None) => return,
+                Err(e) => {
+                    // delivered after the leaves before it, nothing follows
+                    read_ahead.fail(e);
+                    self.todo.clear();
+                    self.staged.clear();
+                    return;
+                }
+            }
         }
     }
 }
 
+/// The parts making up the payload of a leaf block.
+pub(crate) struct LeafParts<'a> {
+    pub leaf: &'a LeafHeader,
+    /// timestamp table, empty if no timestamps were given
+    pub times: &'a [u8],
+    /// signature trailer, empty if the leaf is not signed
+    pub signature: &'a [u8],
+    /// compressed jump table and event data
+    pub compressed: &'a [u8],
+}
+
+/// Split the payload of the given leaf block into its parts.
+pub(crate) fn leaf_parts<'a>(file: &'a MmapFile, header: &'a BlockHeader) -> Fallible<LeafParts<'a>> {
+    let leaf: &LeafHeader = file.stream_after(header)?;
+    let length = u32_to_usize(header.length()).saturating_sub(LeafHeader::LEN);
+    let payload = file.stream_bytes_after(leaf, length)?;
+    let (times, rest) = payload.split_at(leaf.times_len().min(payload.len()));
+    let (signature, compressed) = rest.split_at(leaf.signature_len().min(rest.len()));
+    Ok(LeafParts { leaf, times, signature, compressed })
+}
+
+/// Obtain the decompressed contents of the given leaf block, either from the cache or from the file.
+pub(crate) fn decompress(store: &Store, header: &BlockHeader, prio: bool) -> Fallible<Arc<[u8]>> {
+    debug_assert!(header.level() == 0);
+    let key = (store.id, store.file.stream_offset(header)?);
+    let bytes = store.cache.lock().unwrap_or_else(PoisonError::into_inner).get(key);
+    if let Some(bytes) = bytes {
+        tracing::trace!(?key, "cache hit");
+        Ok(bytes)
+    } else {
+        tracing::trace!(?key, prio, "cache miss");
+        store.file.verify_block(key.1)?;
+        let bytes = Arc::<[u8]>::from(decode_leaf(store, header)?);
+        store.cache.lock().unwrap_or_else(PoisonError::into_inner).put(key, bytes.clone(), prio);
+        Ok(bytes)
+    }
+}
+
+/// Decrypt (if needed) and decompress the contents of the given leaf block.
+pub(crate) fn decode_leaf(store: &Store, header: &BlockHeader) -> Fallible<Vec<u8>> {
+    let parts = leaf_parts(&store.file, header)?;
+    let codec = parts.leaf.codec();
+    let dict = match parts.leaf.dict() {
+        0 => None,
+        dict_id => Some(store.dictionary(dict_id)?),
+    };
+    if header.key_id() == 0 {
+        return codec::decompress(&store.codec, codec, parts.compressed, dict.as_deref());
+    }
+    let plain = decrypt(&store.key(header.key_id())?, parts.leaf.as_slice(), parts.compressed)?;
+    codec::decompress(&store.codec, codec, &plain, dict.as_deref())
+}
+
 impl<'a> Iterator for RangeIter<'a> {
     type Item = Fallible<LeafSlice>;
 
@@ -163,96 +266,412 @@ impl<'a> Iterator for RangeIter<'a> {
         if self.done {
             return None;
         }
-        if self.todo.is_empty() {
-            // rest is in staging area
-            self.done = true;
-            if self.start_idx > self.end_idx {
-                return None;
-            }
-            let head = handle_err!(self.file.staging_at::<StagingHeader>(0), ());
-            let head_start = head.start_idx();
-            let head_count = u64::from(head.count());
-            let start = self.start_idx - head_start;
-            if start >= head_count {
-                return None;
-            }
-            let end = (self.end_idx - head_start).min(head_count - 1);
-            let bytes = handle_err!(self.file.staging_bytes(StagingHeader::LEN, self.file.staging_len()), ());
-            let base = u32_to_usize(self.block_event_limit) * JumpEntry::LEN;
-            return Some(Ok(LeafSlice::new(bytes.into(), start, end, base)));
-        }
-        let mut offset = *self.todo.last().unwrap();
-        loop {
-            let block: &BlockHeader = handle_err!(self.file.stream_at(offset), self.done = true);
-            if block.level() == 0 {
-                if offset == *self.todo.last().unwrap() {
-                    self.todo.pop();
-                }
-                let bytes = handle_err!(self.decompress(block, false), self.done = true);
-                let leaf: &LeafHeader = handle_err!(self.file.stream_after(block), self.done = true);
-                let base = u32_to_usize(leaf.count() + 1) * JumpEntry::LEN;
-                let iter = LeafSlice::new(
+        // the workers need the lock while the leaf is awaited
+        let popped = if self.read_ahead.is_some() {
+            self.fill_read_ahead();
+            self.read_ahead.as_mut().and_then(ReadAhead::pop)
+        } else {
+            None
+        };
+        let lock = self.store.read_lock();
+        let _guard = lock.as_deref().map(FileLock::read);
+        let next = if self.read_ahead.is_some() {
+            popped
+        } else {
+            self.next_leaf().transpose().map(|leaf| {
+                let (offset, start_idx) = leaf?;
+                Ok((offset, start_idx, self.decompress(self.store.file.stream_at(offset)?, false)?))
+            })
+        };
+        match next {
+            Some(Ok((offset, start_idx, bytes))) => {
+                let block: &BlockHeader = handle_err!(self.store.file.stream_at(offset), self.done = true);
+                let leaf: &LeafHeader = handle_err!(self.store.file.stream_after(block), self.done = true);
+                let layout = Layout::of_leaf(leaf, &bytes);
+                let slice = LeafSlice::with_layout(
                     bytes,
-                    self.start_idx - leaf.start_idx(),
+                    leaf.start_idx(),
+                    start_idx - leaf.start_idx(),
                     (self.end_idx - leaf.start_idx()).min(u64::from(leaf.count()) - 1),
-                    base,
+                    layout,
                 );
-                self.start_idx = leaf.start_idx() + u64::from(leaf.count());
-                return Some(Ok(iter));
-            } else {
-                let branch: &BranchHeader = handle_err!(self.file.stream_after(block), self.done = true);
-                if branch.end_idx() <= self.start_idx || self.start_idx > self.end_idx {
-                    self.todo.pop();
-                    offset = *self.todo.last()?;
-                    continue;
+                Some(Ok(handle_err!(slice.with_blobs(&self.store), self.done = true)))
+            }
+            Some(Err(e)) => {
+                self.done = true;
+                Some(Err(e))
+            }
+            None => {
+                // rest is in staging areas
+                let slice = self.staged.pop();
+                self.done = slice.is_none();
+                slice.map(Ok)
+            }
+        }
+    }
+}
+
+/// Iterator over the leaves covering a range of event indices, starting with the newest.
+///
+/// Each [`LeafSlice`] still yields its events in ascending order, use `.iter().rev()` to obtain
+/// them newest first or [`RevEventIter`] for the events with their indices in descending order.
+pub struct RevRangeIter<'a> {
+    store: StoreRef<'a>,
+    done: bool,
+    /// first event index to deliver
+    start_idx: u64,
+    /// next (i.e. highest remaining) event index to deliver
+    end_idx: u64,
+    /// staged events to be delivered before the compressed blocks, last one first
+    staged: SmallVec<[LeafSlice; 2]>,
+    /// offset of the next leaf block to deliver
+    next_leaf: u64,
+}
+
+impl<'a> RevRangeIter<'a> {
+    /// Iterate over the range as stored now, copying the staged events right away like [`RangeIter`] does.
+    pub(crate) fn new(store: StoreRef<'a>, last_block: u64, range: impl RangeBounds<u64>) -> Fallible<Self> {
+        let file = &store.file;
+
+        let (start_idx, end_idx) = index_bounds(range);
+        let staging_start = store.staged_start_idx()?;
+        let next_leaf = if start_idx <= end_idx && start_idx < staging_start {
+            find_leaf(file, last_block, end_idx.min(staging_start - 1))?.unwrap_or(u64::MAX)
+        } else {
+            u64::MAX
+        };
+        let mut staged = SmallVec::new();
+        for area in [Staging::Sealed, Staging::Active] {
+            staged.extend(staging_slice(&store, area, start_idx, end_idx)?);
+        }
+
+        Ok(Self {
+            store,
+            done: start_idx > end_idx,
+            start_idx,
+            end_idx,
+            staged,
+            next_leaf,
+        })
+    }
+}
+
+impl<'a> Iterator for RevRangeIter<'a> {
+    type Item = Fallible<LeafSlice>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        if let Some(slice) = self.staged.pop() {
+            return Some(Ok(slice));
+        }
+        let lock = self.store.read_lock();
+        let _guard = lock.as_deref().map(FileLock::read);
+        if self.next_leaf == u64::MAX {
+            self.done = true;
+            return None;
+        }
+
+        let block: &BlockHeader = handle_err!(self.store.file.stream_at(self.next_leaf), self.done = true);
+        let leaf: &LeafHeader = handle_err!(self.store.file.stream_after(block), self.done = true);
+        let bytes = handle_err!(decompress(&self.store, block, false), self.done = true);
+        let leaf_start = leaf.start_idx();
+        let leaf_end = leaf_start + u64::from(leaf.count()) - 1;
+        let layout = Layout::of_leaf(leaf, &bytes);
+        let slice = LeafSlice::with_layout(
+            bytes,
+            leaf_start,
+            self.start_idx.max(leaf_start) - leaf_start,
+            self.end_idx.min(leaf_end) - leaf_start,
+            layout,
+        );
+        let slice = handle_err!(slice.with_blobs(&self.store), self.done = true);
+
+        if leaf_start <= self.start_idx {
+            self.done = true;
+        } else {
+            // walk the stream backwards to the preceding leaf, skipping branch blocks
+            self.end_idx = leaf_start - 1;
+            let mut offset = block.prev_block();
+            while offset != u64::MAX {
+                let block: &BlockHeader = handle_err!(self.store.file.stream_at(offset), self.done = true);
+                if block.level() == 0 {
+                    break;
                 }
-                let count = (u32_to_usize(block.length()) - BranchHeader::LEN) / IndexEntry::LEN;
-                let mut e: &IndexEntry = handle_err!(self.file.stream_after(branch), self.done = true);
-                for _ in 1..count {
-                    let n: &IndexEntry = handle_err!(self.file.stream_after(e), self.done = true);
-                    if n.start_idx() > self.start_idx {
-                        break;
+                offset = block.prev_block();
+            }
+            self.next_leaf = offset;
+        }
+        Some(Ok(slice))
+    }
+}
+
+/// Translate a range into inclusive bounds, where an empty range has `start > end`.
+pub(crate) fn index_bounds(range: impl RangeBounds<u64>) -> (u64, u64) {
+    if range.start_bound() == Bound::Excluded(&u64::MAX) || range.end_bound() == Bound::Excluded(&0) {
+        (1, 0)
+    } else {
+        let start = match range.start_bound() {
+            Bound::Included(i) => *i,
+            Bound::Excluded(e) => *e + 1,
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(i) => *i,
+            Bound::Excluded(e) => *e - 1,
+            Bound::Unbounded => u64::MAX,
+        };
+        (start, end)
+    }
+}
+
+/// Slice of a staging area covering the given inclusive index range, if any of those events are staged there.
+fn staging_slice(store: &Store, area: Staging, start_idx: u64, end_idx: u64) -> Fallible<Option<LeafSlice>> {
+    let file = &store.file;
+    if area == Staging::Sealed && file.sealed_offset().is_none() {
+        return Ok(None);
+    }
+    let head = file.area_at::<StagingHeader>(area, 0)?;
+    let head_start = head.start_idx();
+    let head_count = u64::from(head.count());
+    if start_idx > end_idx || end_idx < head_start {
+        return Ok(None);
+    }
+    let start = start_idx.saturating_sub(head_start);
+    if start >= head_count {
+        return Ok(None);
+    }
+    let end = (end_idx - head_start).min(head_count - 1);
+    let head = head.lift();
+    if head.key_id != 0 {
+        let (bytes, layout) = store.decrypt_staging(area, &head, start as u32..end as u32 + 1)?;
+        return LeafSlice::with_layout(bytes.into(), head_start, start, end, layout).with_blobs(store).map(Some);
+    }
+    let staging = StagingLayout::of(file, &head);
+    if let Some(size) = staging.fixed {
+        let bytes = file.area_bytes(area, staging.event_start(), file.area_len(area)?)?;
+        let needed = (u32_to_usize(end as u32) + 1) * size;
+        if needed > bytes.len() {
+            return Err(Error::data_corruption(
+                "staged events past end",
+                usize_to_u64(needed),
+                usize_to_u64(bytes.len()),
+            ));
+        }
+        return Ok(Some(LeafSlice::with_layout(
+            bytes.into(),
+            head_start,
+            start,
+            end,
+            Layout::Fixed(size),
+        )));
+    }
+    let bytes = file.area_bytes(area, StagingHeader::LEN, file.area_len(area)?)?;
+    let base = staging.event_start() - StagingHeader::LEN;
+    check_jump_table(bytes, base, start as u32, end as u32)?;
+    LeafSlice::with_layout(bytes.into(), head_start, start, end, Layout::Jump(base))
+        .with_blobs(store)
+        .map(Some)
+}
+
+/// Check that the staged events at positions `start..=end` lie within the given copy of the staging area, which
+/// may be torn if another process was writing to it.
+fn check_jump_table(bytes: &[u8], base: usize, start: u32, end: u32) -> Fallible<()> {
+    let mut prev = 0;
+    for pos in start..=end + 1 {
+        let at = JumpEntry::LEN * u32_to_usize(pos);
+        let offset = match bytes.get(at..at + JumpEntry::LEN) {
+            Some(entry) => u32_to_usize(JumpEntry::from_slice(entry).offset()),
+            None => usize::MAX,
+        };
+        if offset < prev || base.saturating_add(offset) > bytes.len() {
+            return Err(Error::data_corruption(
+                "invalid staged jump entry",
+                usize_to_u64(at),
+                usize_to_u64(bytes.len()),
+            ));
+        }
+        prev = offset;
+    }
+    Ok(())
+}
+
+/// Find the offset of the top-level block containing the given event index.
+pub(crate) fn find_top(file: &MmapFile, last_block: u64, idx: u64) -> Fallible<Option<u64>> {
+    let mut found = None;
+    for block in SearchIter::new(file, last_block) {
+        let (offset, block) = block?;
+        let start_idx = if block.level() == 0 {
+            file.stream_after::<_, LeafHeader>(block)?.start_idx()
+        } else {
+            let branch: &BranchHeader = file.stream_after(block)?;
+            file.stream_after::<_, IndexEntry>(branch)?.start_idx()
+        };
+        if start_idx <= idx {
+            found = Some(offset);
+            break;
+        }
+    }
+    Ok(found)
+}
+
+/// Find the offset of the leaf block containing the given event index by descending from the top-level blocks.
+///
+/// Each branch has 16 children of the level below it. With a fixed event size all leaves hold the same number of
+/// events, so every child of a branch covers the same number of events and the child to descend into follows from
+/// the event index, counted from the branch’s first entry since branches built after truncation may start at any
+/// leaf. The guess is checked against the entries, falling back to searching the branch as done for other files.
+pub(crate) fn find_leaf(file: &MmapFile, last_block: u64, idx: u64) -> Fallible<Option<u64>> {
+    let mut offset = match find_top(file, last_block, idx)? {
+        Some(offset) => offset,
+        None => return Ok(None),
+    };
+    let leaf_events = file.fixed_layout().map(|(_, leaf_events)| u64::from(leaf_events));
+
+    // then descend through the branches down to the leaf
+    loop {
+        let block: &BlockHeader = file.stream_at(offset)?;
+        if block.level() == 0 {
+            return Ok(Some(offset));
+        }
+        let entries = offset + BlockHeader::SIZE + BranchHeader::SIZE;
+        let count = (u64::from(block.length()) - BranchHeader::SIZE) / IndexEntry::SIZE;
+        let start_idx = |pos: u64| -> Fallible<u64> {
+            Ok(file.stream_at::<IndexEntry>(entries + pos * IndexEntry::SIZE)?.start_idx())
+        };
+        // whether entry `pos` is the last one starting at or before idx
+        let holds = |pos: u64| -> Fallible<bool> {
+            Ok(pos < count && start_idx(pos)? <= idx && (pos + 1 == count || start_idx(pos + 1)? > idx))
+        };
+        let guess = match leaf_events {
+            Some(leaf_events) => {
+                let span = leaf_events.saturating_mul(16u64.saturating_pow(block.level() - 1));
+                Some(idx.saturating_sub(start_idx(0)?) / span).filter(|pos| *pos < count)
+            }
+            None => None,
+        };
+        let pos = match guess {
+            Some(pos) if holds(pos)? => pos,
+            _ => {
+                // number of entries starting at or before idx (the first one always does)
+                let (mut lo, mut hi) = (1, count);
+                while lo < hi {
+                    let mid = (lo + hi) / 2;
+                    if start_idx(mid)? <= idx {
+                        lo = mid + 1;
+                    } else {
+                        hi = mid;
                     }
-                    e = n;
                 }
-                offset = e.offset();
+                lo - 1
             }
-        }
+        };
+        offset = file.stream_at::<IndexEntry>(entries + pos * IndexEntry::SIZE)?.offset();
     }
 }
 
 pub struct LeafSlice {
     bytes: Arc<[u8]>,
+    /// event index of the leaf’s first event
+    leaf_start: u64,
     start_idx: u32,
     end_idx: u32,
-    base: usize,
+    layout: Layout,
+    /// contents of the events within the slice that are stored in blobs, by position
+    blobs: Vec<(u32, Arc<[u8]>)>,
 }
 
 impl LeafSlice {
+    /// A slice of the given leaf contents, laid out as a jump table followed by the event data at `base`.
+    ///
+    /// Event indices count from the leaf’s first event, see [`start_idx`](Self::start_idx).
     pub fn new(bytes: Arc<[u8]>, start_idx: u64, end_idx: u64, base: usize) -> Self {
+        Self::with_layout(bytes, 0, start_idx, end_idx, Layout::Jump(base))
+    }
+
+    pub(crate) fn with_layout(bytes: Arc<[u8]>, leaf_start: u64, start_idx: u64, end_idx: u64, layout: Layout) -> Self {
         Self {
             bytes,
+            leaf_start,
             start_idx: start_idx.try_into().unwrap(),
             end_idx: end_idx.try_into().unwrap(),
-            base,
+            layout,
+            blobs: Vec::new(),
         }
     }
 
+    /// Load the events within the slice that are stored in blobs, see [`crate::blob`].
+    pub(crate) fn with_blobs(mut self, store: &Store) -> Fallible<Self> {
+        for pos in self.start_idx..=self.end_idx {
+            if is_blob(&self.bytes, self.layout, pos) {
+                let (from, to) = event_bounds(&self.bytes, self.layout, pos);
+                let blob = store.blob(&self.bytes[from..to], self.leaf_start + u64::from(pos))?;
+                self.blobs.push((pos, blob));
+            }
+        }
+        Ok(self)
+    }
+
+    /// Event index of the first event in this slice.
+    pub fn start_idx(&self) -> u64 {
+        self.leaf_start + u64::from(self.start_idx)
+    }
+
+    /// Event index of the last event in this slice.
+    pub fn end_idx(&self) -> u64 {
+        self.leaf_start + u64::from(self.end_idx)
+    }
+
     pub fn iter(&self) -> LeafIter<'_> {
         LeafIter {
             leaf: &self.bytes,
             pos: self.start_idx,
             last: self.end_idx,
-            base: self.base,
+            layout: self.layout,
+            blobs: &self.blobs,
         }
     }
+
+    /// The event with the given index, if it lies within this slice.
+    pub(crate) fn event_at(&self, idx: u64) -> Option<Event> {
+        let pos = u32::try_from(idx.checked_sub(self.leaf_start)?).ok()?;
+        (self.start_idx..=self.end_idx).contains(&pos).then(|| self.event(pos))
+    }
+
+    fn event(&self, pos: u32) -> Event {
+        let bytes = match blob_at(&self.blobs, pos) {
+            Some(blob) => EventRef::new(blob.clone(), 0, blob.len()),
+            None => {
+                let (from, to) = event_bounds(&self.bytes, self.layout, pos);
+                EventRef::new(self.bytes.clone(), from, to)
+            }
+        };
+        Event { idx: self.leaf_start + u64::from(pos), bytes }
+    }
+}
+
+/// The loaded blob for the event at the given position, if it is stored in one.
+fn blob_at(blobs: &[(u32, Arc<[u8]>)], pos: u32) -> Option<&Arc<[u8]>> {
+    blobs.binary_search_by_key(&pos, |(p, _)| *p).ok().map(|i| &blobs[i].1)
 }
 
 pub struct LeafIter<'a> {
     leaf: &'a Arc<[u8]>,
     pos: u32,
     last: u32,
-    base: usize,
+    layout: Layout,
+    blobs: &'a [(u32, Arc<[u8]>)],
+}
+
+impl<'a> LeafIter<'a> {
+    fn event(&self, pos: u32) -> &'a [u8] {
+        if let Some(blob) = blob_at(self.blobs, pos) {
+            return blob;
+        }
+        let (from, to) = event_bounds(self.leaf, self.layout, pos);
+        &self.leaf[from..to]
+    }
 }
 
 impl<'a> Iterator for LeafIter<'a> {
@@ -262,11 +681,241 @@ impl<'a> Iterator for LeafIter<'a> {
         if self.pos > self.last {
             return None;
         }
-        let pos = 4 * u32_to_usize(self.pos);
-        let from = u32_to_usize(JumpEntry::from_slice(&self.leaf[pos..pos + 4]).pos());
-        let to = u32_to_usize(JumpEntry::from_slice(&self.leaf[pos + 4..pos + 8]).pos());
+        let ret = self.event(self.pos);
         self.pos += 1;
-        let ret = &self.leaf[self.base + from..self.base + to];
         Some(ret)
     }
 }
+
+impl<'a> DoubleEndedIterator for LeafIter<'a> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.pos > self.last {
+            return None;
+        }
+        let ret = self.event(self.last);
+        match self.last.checked_sub(1) {
+            Some(last) => self.last = last,
+            None => self.pos = 1,
+        }
+        Some(ret)
+    }
+}
+
+/// Iterator over the events within a range of indices, see [`EventFile::events`](crate::EventFile::events).
+///
+/// The first error is returned as the last item.
+pub struct EventIter<'a> {
+    leaves: RangeIter<'a>,
+    current: Option<LeafSlice>,
+    /// next position within the current leaf
+    pos: u32,
+    done: bool,
+}
+
+impl<'a> EventIter<'a> {
+    pub fn new(leaves: RangeIter<'a>) -> Self {
+        Self { leaves, current: None, pos: 0, done: false }
+    }
+
+    /// Decompress the next `leaves` leaves ahead of the caller, see [`RangeIter::read_ahead`].
+    pub fn read_ahead(self, leaves: usize) -> Self {
+        Self { leaves: self.leaves.read_ahead(leaves), ..self }
+    }
+}
+
+impl<'a> Iterator for EventIter<'a> {
+    type Item = Fallible<Event>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.done {
+                return None;
+            }
+            if let Some(leaf) = &self.current {
+                if self.pos <= leaf.end_idx {
+                    let event = leaf.event(self.pos);
+                    self.pos += 1;
+                    return Some(Ok(event));
+                }
+            }
+            match self.leaves.next() {
+                Some(Ok(leaf)) => {
+                    self.pos = leaf.start_idx;
+                    self.current = Some(leaf);
+                }
+                Some(Err(e)) => {
+                    self.done = true;
+                    return Some(Err(e));
+                }
+                None => {
+                    self.done = true;
+                    return None;
+                }
+            }
+        }
+    }
+}
+
+/// Iterator over the events within a range of indices newest first, see
+/// [`EventFile::events_rev`](crate::EventFile::events_rev).
+///
+/// The first error is returned as the last item.
+pub struct RevEventIter<'a> {
+    leaves: RevRangeIter<'a>,
+    current: Option<LeafSlice>,
+    /// next position within the current leaf, `None` once it is exhausted
+    pos: Option<u32>,
+    done: bool,
+}
+
+impl<'a> RevEventIter<'a> {
+    pub fn new(leaves: RevRangeIter<'a>) -> Self {
+        Self { leaves, current: None, pos: None, done: false }
+    }
+}
+
+impl<'a> Iterator for RevEventIter<'a> {
+    type Item = Fallible<Event>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.done {
+                return None;
+            }
+            if let (Some(leaf), Some(pos)) = (&self.current, self.pos) {
+                let event = leaf.event(pos);
+                self.pos = pos.checked_sub(1).filter(|pos| *pos >= leaf.start_idx);
+                return Some(Ok(event));
+            }
+            match self.leaves.next() {
+                Some(Ok(leaf)) => {
+                    self.pos = Some(leaf.end_idx);
+                    self.current = Some(leaf);
+                }
+                Some(Err(e)) => {
+                    self.done = true;
+                    return Some(Err(e));
+                }
+                None => {
+                    self.done = true;
+                    return None;
+                }
+            }
+        }
+    }
+}
+
+/// An event together with its index.
+#[derive(Clone, Debug)]
+pub struct Event {
+    idx: u64,
+    bytes: EventRef,
+}
+
+impl Event {
+    pub(crate) fn new(idx: u64, bytes: EventRef) -> Self {
+        Self { idx, bytes }
+    }
+
+    pub fn idx(&self) -> u64 {
+        self.idx
+    }
+
+    pub fn bytes(&self) -> &EventRef {
+        &self.bytes
+    }
+
+    pub fn into_bytes(self) -> EventRef {
+        self.bytes
+    }
+}
+
+impl Deref for Event {
+    type Target = [u8];
+
+    fn deref(&self) -> &Self::Target {
+        &self.bytes
+    }
+}
+
+/// How the events are arranged within a decompressed leaf (or staging area).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Layout {
+    /// jump table starting at byte zero, event data starting at the given position
+    Jump(usize),
+    /// events of the given size back to back, starting at byte zero
+    Fixed(usize),
+}
+
+impl Layout {
+    /// Layout of the given leaf’s decompressed contents.
+    pub fn of_leaf(leaf: &LeafHeader, bytes: &[u8]) -> Self {
+        if leaf.flags() & LEAF_FIXED != 0 {
+            Self::Fixed(bytes.len() / u32_to_usize(leaf.count()).max(1))
+        } else {
+            Self::Jump(u32_to_usize(leaf.count() + 1) * JumpEntry::LEN)
+        }
+    }
+}
+
+/// Byte range of the event at position `pos` within a leaf (or staging area) with the given layout.
+pub(crate) fn event_bounds(leaf: &[u8], layout: Layout, pos: u32) -> (usize, usize) {
+    match layout {
+        Layout::Jump(base) => {
+            let pos = JumpEntry::LEN * u32_to_usize(pos);
+            let from = u32_to_usize(JumpEntry::from_slice(&leaf[pos..pos + 4]).offset());
+            let to = u32_to_usize(JumpEntry::from_slice(&leaf[pos + 4..pos + 8]).offset());
+            (base + from, base + to)
+        }
+        Layout::Fixed(size) => {
+            let from = u32_to_usize(pos) * size;
+            (from, from + size)
+        }
+    }
+}
+
+/// Whether the event at position `pos` within a leaf (or staging area) only holds a reference to a blob.
+pub(crate) fn is_blob(leaf: &[u8], layout: Layout, pos: u32) -> bool {
+    match layout {
+        Layout::Jump(_) => {
+            let pos = JumpEntry::LEN * u32_to_usize(pos + 1);
+            JumpEntry::from_slice(&leaf[pos..pos + 4]).is_blob()
+        }
+        Layout::Fixed(_) => false,
+    }
+}
+
+/// A single event, keeping the decompressed leaf it was taken from alive.
+#[derive(Clone)]
+pub struct EventRef {
+    bytes: Arc<[u8]>,
+    from: usize,
+    to: usize,
+}
+
+impl EventRef {
+    pub(crate) fn new(bytes: Arc<[u8]>, from: usize, to: usize) -> Self {
+        debug_assert!(from <= to && to <= bytes.len());
+        Self { bytes, from, to }
+    }
+}
+
+impl Deref for EventRef {
+    type Target = [u8];
+
+    fn deref(&self) -> &Self::Target {
+        &self.bytes[self.from..self.to]
+    }
+}
+
+impl AsRef<[u8]> for EventRef {
+    fn as_ref(&self) -> &[u8] {
+        self
+    }
+}
+
+impl Debug for EventRef {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("EventRef").field(&&**self).finish()
+    }
+}
@@ -1,28 +1,190 @@
+#[cfg(feature = "tokio")]
+mod async_file;
+mod blob;
 mod cache;
+mod codec;
+mod crypt;
+mod dict;
 mod dump;
 mod error;
 mod formats;
 mod iter;
+mod merkle;
 mod mmap;
+mod read_ahead;
+mod sign;
+mod store;
+mod subscribe;
 
+#[cfg(feature = "tokio")]
+pub use async_file::{AsyncEventFile, EventStream};
+#[cfg(feature = "fbr")]
+pub use cache::SendFbrCache;
 pub use cache::{Cache, NoCache};
+pub use codec::{Codec, Lz4, NoCompression, Zstd};
+pub use crypt::KeyProvider;
 pub use error::Error;
-pub use iter::{LeafIter, LeafSlice, RangeIter};
+pub use iter::{Event, EventIter, EventRef, LeafIter, LeafSlice, RangeIter, RevEventIter, RevRangeIter};
+pub use merkle::{Digest, InclusionProof, ProofStep};
+pub use subscribe::Subscription;
 
+use blob::BLOB_REF_LEN;
+use dict::LoadedDict;
+use ed25519_dalek::{Keypair, PublicKey};
 use error::{ErrCtx, Fallible};
 use formats::{
-    BlockHeader, BranchHeader, HasMagic, IndexEntry, JumpEntry, LeafHeader, StagingHeader, StagingHeaderLifted,
+    BlobHeader, BlockHeader, BranchHeader, DictHeader, EventCheck, EventTime, HasMagic, IndexEntry, JumpEntry,
+    LeafHeader, StagingHeader, StagingHeaderLifted, BLOB_LEVEL, DICT_LEVEL, JUMP_BLOB, LEAF_FIXED, LEAF_SIGNED,
+    LEAF_TIMES,
 };
-use iter::SearchIter;
-use mmap::MmapFile;
+use iter::{find_leaf, leaf_parts, Layout, SearchIter};
+use mmap::{MmapFile, Staging};
+use sign::LeafHash;
 use smallvec::SmallVec;
-use std::{cell::RefCell, io::Write, mem::size_of_val, ops::RangeBounds, path::PathBuf, slice};
+use std::{
+    io,
+    mem::size_of_val,
+    ops::{Range, RangeBounds},
+    path::PathBuf,
+    slice,
+    sync::Arc,
+    thread::{self, JoinHandle},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use store::{StagingLayout, Store};
+use subscribe::Notifier;
+
+/// A full staging area’s events, copied out of the file so that they can be compressed on another thread.
+struct LeafJob {
+    start_idx: u64,
+    count: u32,
+    /// (decrypted) events arranged as described by the layout
+    data: Vec<u8>,
+    layout: Layout,
+    /// timestamp table, empty if no timestamps have been given
+    times: Vec<u8>,
+    min_time: u64,
+    max_time: u64,
+    codec: Arc<dyn Codec>,
+    dict: Option<LoadedDict>,
+    /// dictionary to be stored along with the leaf: id, contents, and number of training samples
+    new_dictionary: Option<(u32, Vec<u8>, u32)>,
+    /// id and value of the key for encrypting the leaf
+    key: Option<(u32, [u8; 32])>,
+    /// key for signing the leaf and hash of the leaf to chain it to
+    signing: Option<(Arc<Keypair>, LeafHash)>,
+}
+
+/// A compressed leaf ready to be appended to the stream.
+struct SealedLeaf {
+    leaf: LeafHeader,
+    times: Vec<u8>,
+    trailer: Vec<u8>,
+    compressed: Vec<u8>,
+    key_id: u32,
+    content_hash: Digest,
+    leaf_hash: Option<LeafHash>,
+    new_dictionary: Option<(u32, Vec<u8>, u32)>,
+}
+
+impl LeafJob {
+    /// Compress, encrypt, hash, and sign the events, without touching the file.
+    fn run(self) -> Fallible<SealedLeaf> {
+        let (compressed, dict_id) = match &self.dict {
+            Some((dict_id, bytes)) if self.codec.supports_dictionary() => {
+                (self.codec.compress_with_dictionary(&self.data, bytes)?, *dict_id)
+            }
+            _ => (self.codec.compress(&self.data)?, 0),
+        };
+        let events_root = merkle::merkle_root(&merkle::event_hashes(&self.data, self.layout, self.count)?);
+        let content_hash = merkle::leaf_content(self.start_idx, self.count, &events_root);
+
+        let flags = if self.times.is_empty() { 0 } else { LEAF_TIMES };
+        let flags = if self.signing.is_some() { flags | LEAF_SIGNED } else { flags };
+        let flags = if matches!(self.layout, Layout::Fixed(_)) { flags | LEAF_FIXED } else { flags };
+        let leaf = LeafHeader::new(
+            self.start_idx,
+            unix_millis(SystemTime::now()),
+            self.min_time,
+            self.max_time,
+            self.count,
+            flags,
+            self.codec.id(),
+            dict_id,
+        );
+
+        // encryption is bound to the leaf header
+        let (key_id, compressed) = match &self.key {
+            Some((key_id, key)) => (*key_id, crypt::encrypt(key, leaf.as_slice(), &compressed)?),
+            None => (0, compressed),
+        };
+
+        let (trailer, leaf_hash) = match &self.signing {
+            Some((key, prev)) => {
+                let hash = sign::leaf_hash(&leaf, &self.times, prev, &compressed);
+                (sign::sign(key, prev, &hash).to_vec(), Some(hash))
+            }
+            None => (Vec::new(), None),
+        };
+
+        Ok(SealedLeaf {
+            leaf,
+            times: self.times,
+            trailer,
+            compressed,
+            key_id,
+            content_hash,
+            leaf_hash,
+            new_dictionary: self.new_dictionary,
+        })
+    }
+}
+
+/// Policy for syncing appended events to disk.
+///
+/// Compressed blocks are always synced when they are written, this policy governs the events
+/// that are still held in the staging area.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Durability {
+    /// leave syncing to the operating system or explicit calls to [`EventFile::flush`]
+    None,
+    /// sync once the given number of events have been appended or the given time has passed
+    /// since the last sync, whichever comes first (checked when appending)
+    Periodic { events: u32, interval: Duration },
+    /// sync after each append
+    Always,
+}
+
+/// Policy for dropping old events, enforced whenever the staging area has been compressed.
+///
+/// Only whole compressed blocks are dropped, see [`EventFile::truncate_before`], so slightly more data may be
+/// retained than demanded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Retention {
+    /// keep all events
+    Forever,
+    /// keep (at least) the given number of most recent events
+    Events(u64),
+    /// keep the most recent compressed blocks that fit into the given number of bytes
+    Bytes(u64),
+    /// keep blocks that were written no longer than the given duration ago
+    Age(Duration),
+}
 
 pub struct EventFileConfig {
     user_version: u32,
     compression_threshold: usize,
     block_event_limit: u32,
     cache: Box<dyn Cache>,
+    durability: Durability,
+    retention: Retention,
+    signing_key: Option<Keypair>,
+    key_provider: Option<Arc<dyn KeyProvider>>,
+    codec: Arc<dyn Codec>,
+    dictionary_training: Option<(u64, usize)>,
+    background_compression: bool,
+    blob_threshold: usize,
+    fixed_event_size: Option<u32>,
 }
 
 impl EventFileConfig {
@@ -32,6 +194,15 @@ impl EventFileConfig {
             compression_threshold: 100000,
             block_event_limit: 20000,
             cache: Box::new(NoCache),
+            durability: Durability::None,
+            retention: Retention::Forever,
+            signing_key: None,
+            key_provider: None,
+            codec: Arc::new(Zstd::default()),
+            dictionary_training: None,
+            background_compression: false,
+            blob_threshold: usize::MAX,
+            fixed_event_size: None,
         }
     }
 
@@ -46,14 +217,107 @@ impl EventFileConfig {
     pub fn cache(self, cache: Box<dyn Cache>) -> Self {
         Self { cache, ..self }
     }
+
+    pub fn durability(self, durability: Durability) -> Self {
+        Self { durability, ..self }
+    }
+
+    pub fn retention(self, retention: Retention) -> Self {
+        Self { retention, ..self }
+    }
+
+    /// Sign each compressed block with the given key, chaining it to the hash of the previous block, so that
+    /// [`EventFile::verify`] can detect any later modification.
+    ///
+    /// Events in the staging area are not covered until they are compressed into a block.
+    pub fn signing_key(self, signing_key: Keypair) -> Self {
+        Self { signing_key: Some(signing_key), ..self }
+    }
+
+    /// Encrypt compressed blocks and staged events with the provider’s current key, and decrypt them with the
+    /// key recorded alongside.
+    ///
+    /// Hashes and signatures are unaffected, see [`EventFile::root_hash`]; timestamps and block metadata remain
+    /// unencrypted.
+    pub fn key_provider(self, key_provider: Arc<dyn KeyProvider>) -> Self {
+        Self { key_provider: Some(key_provider), ..self }
+    }
+
+    /// Compression for new blocks, [`Zstd`] at level 21 by default.
+    ///
+    /// Blocks written with any of this crate’s codecs remain readable after changing this setting.
+    pub fn codec(self, codec: Arc<dyn Codec>) -> Self {
+        Self { codec, ..self }
+    }
+
+    /// Train a dictionary of at most `max_size` bytes on the first `events` events once they have been appended
+    /// and store it in the file, unless it already holds one.
+    ///
+    /// Leaves compressed from then on use the dictionary if the [`codec`](Self::codec) supports it, which
+    /// greatly improves the compression of small events with shared structure. See also
+    /// [`EventFile::set_dictionary`] and [`EventFile::train_dictionary`].
+    pub fn dictionary_training(self, events: u64, max_size: usize) -> Self {
+        Self { dictionary_training: Some((events, max_size)), ..self }
+    }
+
+    /// Compress full staging areas on a background thread while appends continue in a second staging area,
+    /// avoiding the latency spike of compressing within [`EventFile::append`].
+    ///
+    /// The compressed leaf is published once it has been written, see [`EventFile::finish_compression`]; until
+    /// then its events are read from the sealed staging area.
+    pub fn background_compression(self, background_compression: bool) -> Self {
+        Self { background_compression, ..self }
+    }
+
+    /// Store events larger than `blob_threshold` bytes in blocks of their own, each compressed separately, so
+    /// that they do not inflate the staging area; disabled by default.
+    ///
+    /// Only a short reference is staged in the event’s place, reading remains transparent. Appending such an event
+    /// waits for a [background compression](Self::background_compression) to finish.
+    pub fn blob_threshold(self, blob_threshold: usize) -> Self {
+        Self { blob_threshold, ..self }
+    }
+
+    /// Accept only events of exactly `size` bytes, e.g. metrics samples or hashes, failing with
+    /// [`Error::WrongEventSize`] otherwise.
+    ///
+    /// Leaves and staging areas then hold the events back to back without a jump table, so that an event’s
+    /// position is computed from its index. Every leaf holds `block_event_limit - 1` events regardless of the
+    /// `compression_threshold`, so that the leaf holding an event is found by arithmetic as well; batches must
+    /// therefore not cross a leaf boundary, see [`EventFile::append_batch`]. No events are stored in blobs.
+    ///
+    /// This layout is recorded when the file is created: an existing file keeps its fixed event size and leaf
+    /// size, or lack thereof, regardless of this setting (and of `block_event_limit` if it has them).
+    pub fn fixed_event_size(self, size: u32) -> Self {
+        Self { fixed_event_size: Some(size), ..self }
+    }
 }
 
 pub struct EventFile {
-    file: MmapFile,
-    id: u32,
+    store: Store,
     compression_threshold: usize,
     block_event_limit: u32,
-    cache: RefCell<Box<dyn Cache>>,
+    dropped_events: u32,
+    durability: Durability,
+    retention: Retention,
+    signing_key: Option<Arc<Keypair>>,
+    /// hash of the latest leaf if known, needed for signing the next one
+    last_leaf_hash: Option<LeafHash>,
+    /// number of events and maximum size for training a dictionary, cleared once attempted
+    dictionary_training: Option<(u64, usize)>,
+    /// dictionary to be stored with the next compressed block, with the number of events it was trained on
+    pending_dictionary: Option<(Vec<u8>, u32)>,
+    background_compression: bool,
+    /// events larger than this are stored in blob blocks
+    blob_threshold: usize,
+    /// size of all events if the file was created without jump tables
+    fixed_event_size: Option<u32>,
+    /// compression of the sealed staging area running in the background
+    compressing: Option<JoinHandle<Fallible<SealedLeaf>>>,
+    /// staging event count at the last sync
+    synced: u32,
+    last_sync: Instant,
+    notifier: Arc<Notifier>,
 }
 
 impl EventFile {
@@ -63,85 +327,592 @@ impl EventFile {
             compression_threshold,
             block_event_limit,
             cache,
+            durability,
+            retention,
+            signing_key,
+            key_provider,
+            codec,
+            dictionary_training,
+            background_compression,
+            blob_threshold,
+            fixed_event_size,
         } = config;
+        // an existing file keeps the layout it was created with
+        let leaf_events = block_event_limit.saturating_sub(1).max(1);
+        let file = MmapFile::new(path, user_version, fixed_event_size.map(|size| (size, leaf_events)))?;
+        let (fixed_event_size, block_event_limit) = match file.fixed_layout() {
+            Some((size, leaf_events)) => (Some(size), leaf_events + 1),
+            None => (None, block_event_limit),
+        };
         let mut ret = Self {
-            file: MmapFile::new(path, user_version)?,
-            id,
+            store: Store::new(file, id, cache, key_provider, codec),
             compression_threshold,
             block_event_limit,
-            cache: RefCell::new(cache),
+            dropped_events: 0,
+            durability,
+            retention,
+            signing_key: signing_key.map(Arc::new),
+            last_leaf_hash: None,
+            dictionary_training,
+            pending_dictionary: None,
+            background_compression,
+            blob_threshold,
+            fixed_event_size,
+            compressing: None,
+            synced: 0,
+            last_sync: Instant::now(),
+            notifier: Arc::default(),
         };
-        if ret.file.staging_len() == 0 {
+        if ret.store.file.staging_len() == 0 {
             // fresh file
-            ret.prep_staging(u64::MAX, 0)?;
+            ret.prep_staging(u64::MAX, 0, Digest::default())?;
+        } else {
+            ret.dropped_events = ret.recover_staging()?;
+            ret.synced = ret.staging_header()?.count;
         }
+        // a staging area sealed before closing the file is compressed right away
+        ret.finish_compression()?;
+        ret.notifier.notify(ret.next_index()?);
         Ok(ret)
     }
 
-    fn prep_staging(&mut self, last_block: u64, start_idx: u64) -> Fallible<()> {
-        let size = self.staging_event_start() + self.compression_threshold;
-        self.file.clear_staging();
-        self.file.ensure_staging_len(size)?;
-        self.file.staging_put(0, StagingHeader::new(last_block, start_idx, 0, self.block_event_limit))?;
-        self.flush()?;
+    /// Open an existing file for reading only, e.g. while another process is appending to it.
+    ///
+    /// Only the `user_version`, `cache`, `key_provider`, and `codec` are taken from the configuration.
+    pub fn open_read_only(id: u32, path: PathBuf, config: EventFileConfig) -> Fallible<EventFileReader> {
+        let file = MmapFile::open_read_only(path, config.user_version)?;
+        Ok(EventFileReader {
+            store: Store::new(file, id, config.cache, config.key_provider, config.codec),
+        })
+    }
+
+    /// Check that `event` was stored at index `idx` in the log identified by `root`, see
+    /// [`inclusion_proof`](Self::inclusion_proof).
+    pub fn verify_inclusion(root: &Digest, idx: u64, event: &[u8], proof: &InclusionProof) -> bool {
+        proof.verify(root, idx, event)
+    }
+
+    /// Follow this file starting at index `from_idx`, see [`Subscription`].
+    ///
+    /// The subscription reads through a [`reader`](Self::reader) and can be moved to another thread; it is
+    /// woken whenever events are appended through this `EventFile`.
+    pub fn subscribe(&self, from_idx: u64) -> Fallible<Subscription> {
+        Ok(Subscription::notified(self.reader(), self.notifier.clone(), from_idx))
+    }
+
+    /// Obtain a read-only handle that shares this file’s mapping and cache.
+    ///
+    /// The handle can be cloned and used from other threads while this `EventFile` keeps appending, see
+    /// [`EventFileReader`].
+    pub fn reader(&self) -> EventFileReader {
+        EventFileReader { store: self.store.share() }
+    }
+
+    /// Number of events that were found incompletely written in the staging area when opening
+    /// this file and have therefore been discarded.
+    pub fn dropped_events(&self) -> u32 {
+        self.dropped_events
+    }
+
+    /// Truncate the staging area back to the last event that was fully written and committed.
+    fn recover_staging(&mut self) -> Fallible<u32> {
+        let header = self.staging_header()?;
+        let layout = self.staging_layout()?;
+        let start = layout.event_start();
+        let data_len = self.store.file.staging_len() - start;
+        let mut committed = 0;
+        for idx in 0..header.count {
+            let i = u32_to_usize(idx);
+            let (from, to, _) = layout.event_range(&self.store.file, Staging::Active, i)?;
+            if from > to || to > data_len {
+                break;
+            }
+            let check = self.store.file.staging_at::<EventCheck>(layout.check_idx(i))?.lift();
+            let bytes = self.store.file.staging_bytes(start + from, start + to)?;
+            if check.end <= idx || crc32fast::hash(bytes) != check.crc {
+                break;
+            }
+            if check.end == idx + 1 {
+                committed = idx + 1;
+            }
+        }
+        let dropped = header.count - committed;
+        if dropped > 0 {
+            tracing::warn!(
+                dropped,
+                start_idx = header.start_idx,
+                "discarding torn events from staging area"
+            );
+            self.store.file.staging_at_mut::<StagingHeader>(0)?.set_count(committed);
+            self.flush()?;
+        }
+        Ok(dropped)
+    }
+
+    fn prep_staging(&mut self, last_block: u64, start_idx: u64, last_hash: Digest) -> Fallible<()> {
+        let key_id = self.store.current_key_id();
+        let layout = StagingLayout::new(&self.store.file, self.block_event_limit, key_id);
+        // events of a fixed size fill the area exactly, otherwise it is compressed once the threshold is reached
+        let data_len = match layout.fixed {
+            Some(size) => size * u32_to_usize(self.block_event_limit - 1),
+            None => self.compression_threshold,
+        };
+        self.store.file.clear_staging()?;
+        self.store.file.ensure_staging_len(layout.event_start() + data_len)?;
+        let header = StagingHeader::new(last_block, start_idx, 0, self.block_event_limit, key_id, last_hash);
+        self.store.file.staging_put(0, header)?;
+        self.store.file.flush_staging(0, layout.jump_end(0))?;
+        // the header only refers to the new area once it is in place
+        self.store.file.publish_staging()?;
+        self.synced = 0;
+        self.last_sync = Instant::now();
         Ok(())
     }
 
-    fn staging_event_start(&self) -> usize {
-        self.staging_jump_idx(u32_to_usize(self.block_event_limit))
+    /// Sync the events appended since the last sync, including their jump and check entries.
+    fn sync_staging(&mut self) -> Fallible<()> {
+        let count = self.staging_header()?.count;
+        let (from, to) = (u32_to_usize(self.synced), u32_to_usize(count));
+        if from < to {
+            let layout = self.staging_layout()?;
+            let start = layout.event_start();
+            let data_from = layout.data_len(&self.store.file, Staging::Active, from)?;
+            let data_to = layout.data_len(&self.store.file, Staging::Active, to)?;
+            self.store.file.flush_staging(start + data_from, start + data_to)?;
+            self.store.file.flush_staging(layout.jump_end(from), layout.jump_end(to))?;
+            self.store.file.flush_staging(layout.check_idx(from), layout.check_idx(to))?;
+            self.store.file.flush_staging(layout.time_idx(from), layout.time_idx(to))?;
+            self.store.file.flush_staging(0, StagingHeader::LEN)?;
+        }
+        self.synced = count;
+        self.last_sync = Instant::now();
+        Ok(())
     }
 
-    fn staging_jump_idx(&self, idx: usize) -> usize {
-        StagingHeader::LEN + idx * 4
+    /// Layout of the active staging area.
+    fn staging_layout(&self) -> Fallible<StagingLayout> {
+        Ok(StagingLayout::of(&self.store.file, &self.staging_header()?))
     }
 
     fn staging_header(&self) -> Fallible<StagingHeaderLifted> {
-        self.file.staging_at::<StagingHeader>(0).map(|x| x.lift())
+        self.store.staging_header()
+    }
+
+    /// Index that will be assigned to the next appended event.
+    pub fn next_index(&self) -> Fallible<u64> {
+        self.store.next_index()
     }
 
-    pub fn append(&mut self, event: &[u8]) -> Fallible<()> {
+    /// Index of the oldest event that has not been dropped by [`truncate_before`](Self::truncate_before).
+    pub fn first_index(&self) -> Fallible<u64> {
+        self.store.first_index()
+    }
+
+    /// Index before which all events have been synced to disk, either when compressing them or as demanded by the
+    /// [`Durability`] policy; explicit [`flush`](Self::flush)es are not taken into account.
+    pub fn synced_index(&self) -> Fallible<u64> {
+        Ok(self.staging_header()?.start_idx + u64::from(self.synced))
+    }
+
+    /// Number of events retained in this file, from [`first_index`](Self::first_index) up to (excluding)
+    /// [`next_index`](Self::next_index).
+    pub fn len(&self) -> Fallible<u64> {
+        Ok(self.next_index()? - self.first_index()?)
+    }
+
+    pub fn is_empty(&self) -> Fallible<bool> {
+        Ok(self.len()? == 0)
+    }
+
+    /// Append an event, returning the index assigned to it.
+    pub fn append(&mut self, event: &[u8]) -> Fallible<u64> {
+        self.append_with_time(event, 0)
+    }
+
+    /// Append an event with a caller-defined timestamp (e.g. milliseconds since the UNIX epoch), returning the
+    /// index assigned to it.
+    ///
+    /// Timestamps can be searched with [`seek_time`](Self::seek_time); events appended without one have
+    /// timestamp 0.
+    pub fn append_with_time(&mut self, event: &[u8], time: u64) -> Fallible<u64> {
+        self.check_event_size(event)?;
+        let lock = self.store.lock.clone();
+        let _guard = lock.write();
+        let blob = self.write_blob(self.next_index()?, event)?;
         let header = self.staging_header()?;
         let count = header.count;
-        let idx = self.staging_jump_idx(u32_to_usize(count));
-        let offset = self.file.staging_at::<JumpEntry>(idx)?.pos();
-        let start = self.staging_event_start() + u32_to_usize(offset);
-        self.file.ensure_staging_len(start + event.len())?;
-        self.file.staging_write(start, event)?;
-        let new_len = offset + event.len() as u32;
-        self.file.staging_put(idx + 4, JumpEntry::new(new_len))?;
-        self.file.staging_at_mut::<StagingHeader>(0)?.set_count(count + 1);
-        if count + 2 >= header.capacity || u32_to_usize(new_len) >= self.compression_threshold {
+        let new_len = self.write_event(count, event, blob.as_ref(), count + 1, time)?;
+        self.store.file.staging_at_mut::<StagingHeader>(0)?.set_count(count + 1);
+        self.appended(header.capacity, count + 1, new_len)?;
+        self.notifier.notify(header.start_idx + u64::from(count + 1));
+        Ok(header.start_idx + u64::from(count))
+    }
+
+    /// Append an event only if it would be assigned the `expected` index, i.e. if no other event has been
+    /// appended since the caller last looked; fails with [`Error::WrongOffset`] otherwise.
+    pub fn append_if(&mut self, expected: u64, event: &[u8]) -> Fallible<u64> {
+        let found = self.next_index()?;
+        if found != expected {
+            return Err(Error::wrong_offset(expected, found));
+        }
+        self.append(event)
+    }
+
+    /// Append a group of events that becomes visible — also after a crash — either completely or not at all.
+    ///
+    /// Returns the range of indices assigned to the events. The batch must fit into a single staging area,
+    /// i.e. it may hold at most one event less than the configured `block_event_limit`. With a
+    /// [fixed event size](EventFileConfig::fixed_event_size) it must also fit into the room left in the current
+    /// one, i.e. it must not cross a multiple of `block_event_limit - 1` events.
+    pub fn append_batch<'a>(&mut self, events: impl IntoIterator<Item = &'a [u8]>) -> Fallible<Range<u64>> {
+        self.append_batch_with_time(events.into_iter().map(|event| (event, 0)))
+    }
+
+    /// Append a group of events with caller-defined timestamps like [`append_batch`](Self::append_batch), see
+    /// [`append_with_time`](Self::append_with_time).
+    pub fn append_batch_with_time<'a>(
+        &mut self, events: impl IntoIterator<Item = (&'a [u8], u64)>,
+    ) -> Fallible<Range<u64>> {
+        let (events, times): (SmallVec<[&[u8]; 16]>, SmallVec<[u64; 16]>) = events.into_iter().unzip();
+        let mut header = self.staging_header()?;
+        let limit = header.capacity - 1;
+        let size = u32::try_from(events.len()).ok().filter(|n| *n <= limit);
+        let size = size.ok_or(Error::batch_too_large(events.len(), limit))?;
+        for event in &events {
+            self.check_event_size(event)?;
+        }
+        let lock = self.store.lock.clone();
+        let _guard = lock.write();
+        if size > limit - header.count {
+            // compressing early would leave a leaf with fewer events than all others
+            if self.fixed_event_size.is_some() {
+                return Err(Error::batch_too_large(events.len(), limit - header.count));
+            }
+            self.compress()?;
+            header = self.staging_header()?;
+        }
+        let start = header.start_idx + u64::from(header.count);
+        if size == 0 {
+            return Ok(start..start);
+        }
+
+        // blobs must be durable before the events referring to them
+        let blobs = (start..)
+            .zip(&events)
+            .map(|(idx, event)| self.write_blob(idx, event))
+            .collect::<Fallible<SmallVec<[_; 16]>>>()?;
+        let end = header.count + size;
+        let mut new_len = 0;
+        for (((count, event), blob), time) in (header.count..).zip(events).zip(&blobs).zip(times) {
+            new_len = self.write_event(count, event, blob.as_ref(), end, time)?;
+        }
+        self.store.file.staging_at_mut::<StagingHeader>(0)?.set_count(end);
+        self.appended(header.capacity, end, new_len)?;
+        self.notifier.notify(start + u64::from(size));
+        Ok(start..start + u64::from(size))
+    }
+
+    /// Write event bytes, jump entry, check entry, and timestamp for the event at staging position `count`, staging
+    /// the reference instead of the bytes if the event has been stored in a blob.
+    ///
+    /// The event only becomes committed once the staging count reaches `end`. Returns the new length of the
+    /// staging area’s event data.
+    fn write_event(
+        &mut self, count: u32, event: &[u8], blob: Option<&[u8; BLOB_REF_LEN]>, end: u32, time: u64,
+    ) -> Fallible<u32> {
+        let header = self.staging_header()?;
+        let (event, flags) = match blob {
+            Some(reference) => (&reference[..], JUMP_BLOB),
+            None => (event, 0),
+        };
+        let sealed;
+        let event = match header.key_id {
+            0 => event,
+            key_id => {
+                let aad = (header.start_idx + u64::from(count)).to_be_bytes();
+                sealed = crypt::encrypt(&self.store.key(key_id)?, &aad, event)?;
+                &sealed
+            }
+        };
+        let layout = StagingLayout::of(&self.store.file, &header);
+        let pos = u32_to_usize(count);
+        let offset = layout.data_len(&self.store.file, Staging::Active, pos)?;
+        let new_len = u32::try_from(offset + event.len())
+            .ok()
+            .filter(|len| len & JUMP_BLOB == 0)
+            .ok_or(Error::numeric_overflow("staging area > 2GiB"))?;
+        let start = layout.event_start() + offset;
+        self.store.file.ensure_staging_len(start + event.len())?;
+        self.store.file.staging_write(start, event)?;
+        if layout.fixed.is_none() {
+            self.store.file.staging_put(layout.jump_idx(pos + 1), JumpEntry::new(new_len | flags))?;
+        }
+        let check = EventCheck::new(crc32fast::hash(event), end);
+        self.store.file.staging_put(layout.check_idx(pos), check)?;
+        self.store.file.staging_put(layout.time_idx(pos), EventTime::new(time))?;
+        Ok(new_len)
+    }
+
+    /// Reject events that do not match the fixed event size, if one is configured.
+    fn check_event_size(&self, event: &[u8]) -> Fallible<()> {
+        match self.fixed_event_size {
+            Some(size) if u32_to_usize(size) != event.len() => Err(Error::wrong_event_size(size, event.len())),
+            _ => Ok(()),
+        }
+    }
+
+    /// Store the event with index `idx` in a blob block if it exceeds the blob threshold, returning the reference
+    /// to be staged in its place.
+    fn write_blob(&mut self, idx: u64, event: &[u8]) -> Fallible<Option<[u8; BLOB_REF_LEN]>> {
+        if event.len() <= self.blob_threshold || self.fixed_event_size.is_some() {
+            return Ok(None);
+        }
+        // the blob is appended to the stream, where a sealed staging area would be in the way
+        self.complete_compression()?;
+        let len = u32::try_from(event.len()).ctx("blob > 4GiB")?;
+        let header = BlobHeader::new(idx, len, self.store.codec.id());
+        let compressed = self.store.codec.compress(event)?;
+        // encryption is bound to the blob header
+        let key_id = self.store.current_key_id();
+        let bytes = match key_id {
+            0 => compressed,
+            _ => crypt::encrypt(&self.store.key(key_id)?, header.as_slice(), &compressed)?,
+        };
+        let length = u32::try_from(BlobHeader::LEN + bytes.len()).ctx("blob > 4GiB")?;
+
+        self.make_room(BlockHeader::LEN + u32_to_usize(length), true)?;
+        let offset = self.store.file.end_offset();
+        let block = BlockHeader::new(u64::MAX, BLOB_LEVEL, length, 0, key_id, Digest::default(), Digest::default());
+        self.store.file.stream_append(block)?;
+        self.store.file.stream_append(header)?;
+        self.store.file.stream_append_bytes(&bytes)?;
+        self.store.file.seal_block(offset)?;
+        self.store.file.flush_stream(offset, self.store.file.end_offset())?;
+        self.store.file.flush_header()?;
+        let used = self.staging_used()?;
+        self.store.file.close_gap(used)?;
+        Ok(Some(blob::blob_ref(offset, &merkle::event_hash(event))))
+    }
+
+    /// Compress or sync the staging area after the count has been raised to `count`, as needed.
+    fn appended(&mut self, capacity: u32, count: u32, new_len: u32) -> Fallible<()> {
+        if self.compressing.as_ref().is_some_and(|handle| handle.is_finished()) {
+            self.complete_compression()?;
+        }
+        // leaves of a fixed event size always hold the same number of events, see `find_leaf`
+        let threshold = self.fixed_event_size.is_none() && u32_to_usize(new_len) >= self.compression_threshold;
+        if count + 1 >= capacity || threshold {
             self.compress()?;
+        } else {
+            match self.durability {
+                Durability::None => {}
+                Durability::Periodic { events, interval } => {
+                    if count - self.synced >= events || self.last_sync.elapsed() >= interval {
+                        self.sync_staging()?;
+                    }
+                }
+                Durability::Always => self.sync_staging()?,
+            }
         }
         Ok(())
     }
 
     fn compress(&mut self) -> Fallible<()> {
+        if self.background_compression {
+            return self.seal();
+        }
         let header = self.staging_header()?;
+        let sealed = self.prepare_leaf(Staging::Active)?.run()?;
+
+        let (last_block, last_hash) = self.write_leaf(sealed, header.last_block, header.last_hash, false)?;
+        self.store.file.reset_staging();
+        self.prep_staging(last_block, header.start_idx + u64::from(header.count), last_hash)?;
+        self.enforce_retention()?;
+
+        Ok(())
+    }
 
-        // compress jump table and event data
-        let mut encoder = zstd::Encoder::new(Vec::new(), 21).ctx("creating encoder")?;
-        let from = self.staging_jump_idx(0);
-        let to = self.staging_jump_idx(u32_to_usize(header.count));
-        let jump_table = self.file.staging_bytes(from, to + 4)?;
-        encoder.write_all(jump_table).ctx("compressing")?;
-        let end = self.staging_event_start() + u32_to_usize(self.file.staging_at::<JumpEntry>(to)?.pos());
-        let event_data = self.file.staging_bytes(self.staging_event_start(), end)?;
-        encoder.write_all(event_data).ctx("compressing")?;
-        let compressed = encoder.finish().ctx("compressing")?;
+    /// Seal the full staging area and compress it on a background thread while appends go to a new one.
+    fn seal(&mut self) -> Fallible<()> {
+        self.complete_compression()?;
+        let header = self.staging_header()?;
+        let job = self.prepare_leaf(Staging::Active)?;
+
+        // the sealed events must be durable before the file header refers to them
+        self.sync_staging()?;
+        let used = self.staging_used()?;
+        let next = self.store.file.staging_offset() + ((usize_to_u64(used) + 7) & !7);
+        self.store.file.seal_staging(next);
+        self.prep_staging(header.last_block, header.start_idx + u64::from(header.count), header.last_hash)?;
+
+        self.compressing = Some(thread::spawn(move || job.run()));
+        Ok(())
+    }
+
+    /// Wait for the staging area sealed for background compression to be written as a leaf, see
+    /// [`EventFileConfig::background_compression`].
+    ///
+    /// This happens automatically when appending once the compression has finished, and before the staging area
+    /// is sealed again; a sealed area left behind by a crash is compressed when opening the file.
+    pub fn finish_compression(&mut self) -> Fallible<()> {
+        let lock = self.store.lock.clone();
+        let _guard = lock.write();
+        self.complete_compression()
+    }
+
+    /// Write the leaf compressed from the sealed staging area, if any, see
+    /// [`finish_compression`](Self::finish_compression). Must be called while holding the file lock.
+    fn complete_compression(&mut self) -> Fallible<()> {
+        let sealed = match self.compressing.take() {
+            Some(handle) => handle.join().map_err(|_| Error::cancelled())??,
+            None if self.store.file.sealed_offset().is_some() => self.prepare_leaf(Staging::Sealed)?.run()?,
+            None => return Ok(()),
+        };
+        let header = self.store.sealed_header()?.ok_or(Error::data_not_present("no sealed staging area", 0, 0))?;
+
+        let (last_block, last_hash) = self.write_leaf(sealed, header.last_block, header.last_hash, true)?;
+        let staging = self.store.file.staging_at_mut::<StagingHeader>(0)?;
+        staging.set_last_block(last_block);
+        staging.set_last_hash(last_hash);
+        self.store.file.flush_staging(0, StagingHeader::LEN)?;
+        // publishes the leaf together with the stream end
+        self.store.file.clear_sealed()?;
+        let used = self.staging_used()?;
+        self.store.file.close_gap(used)?;
+        self.enforce_retention()
+    }
+
+    /// Copy the events of the given staging area out of the file, ready to be compressed into a leaf.
+    fn prepare_leaf(&mut self, area: Staging) -> Fallible<LeafJob> {
+        let header = self.store.file.area_at::<StagingHeader>(area, 0)?.lift();
+
+        // train a dictionary as soon as enough events are available
+        if let Some((events, max_size)) = self.dictionary_training {
+            if header.start_idx + u64::from(header.count) >= events {
+                self.dictionary_training = None;
+                if self.pending_dictionary.is_none() && self.store.dictionary_id()? == 0 {
+                    if let Err(error) = self.train_dictionary(..events, max_size) {
+                        tracing::warn!(%error, "cannot train dictionary");
+                    }
+                }
+            }
+        }
+        // a new dictionary is already used for this leaf
+        let (dict, new_dictionary) = match self.pending_dictionary.take() {
+            Some((bytes, samples)) => {
+                let dict_id = self.store.dictionary_id()? + 1;
+                (Some((dict_id, Arc::<[u8]>::from(&bytes[..]))), Some((dict_id, bytes, samples)))
+            }
+            None => match self.store.dictionary_id()? {
+                0 => (None, None),
+                dict_id => (Some((dict_id, self.store.dictionary(dict_id)?)), None),
+            },
+        };
+
+        // jump table and event data, or only the latter for events of a fixed size
+        let count = u32_to_usize(header.count);
+        let staging = StagingLayout::of(&self.store.file, &header);
+        let (data, layout) = if header.key_id == 0 {
+            let start = staging.event_start();
+            let end = start + staging.data_len(&self.store.file, area, count)?;
+            let events = self.store.file.area_bytes(area, start, end)?;
+            match staging.fixed {
+                Some(size) => (events.to_vec(), Layout::Fixed(size)),
+                None => {
+                    let mut data =
+                        self.store.file.area_bytes(area, staging.jump_idx(0), staging.jump_end(count))?.to_vec();
+                    let layout = Layout::Jump(data.len());
+                    data.extend_from_slice(events);
+                    (data, layout)
+                }
+            }
+        } else {
+            self.store.decrypt_staging(area, &header, 0..header.count)?
+        };
+
+        // the timestamp table is only stored if timestamps have been given
+        let times = self.store.file.area_bytes(area, staging.time_idx(0), staging.time_idx(count))?;
+        let (min_time, max_time) = times
+            .chunks(EventTime::LEN)
+            .map(|t| EventTime::from_slice(t).time())
+            .fold((u64::MAX, 0), |(min, max), t| (min.min(t), max.max(t)));
+        let times = if max_time > 0 { times.to_vec() } else { Vec::new() };
+
+        let key = match self.store.current_key_id() {
+            0 => None,
+            key_id => Some((key_id, self.store.key(key_id)?)),
+        };
+        let signing = match &self.signing_key {
+            Some(key) => {
+                let prev = match self.last_leaf_hash {
+                    Some(hash) => hash,
+                    None => self.stored_last_leaf_hash(&header)?,
+                };
+                Some((key.clone(), prev))
+            }
+            None => None,
+        };
+
+        Ok(LeafJob {
+            start_idx: header.start_idx,
+            count: header.count,
+            data,
+            layout,
+            times,
+            min_time,
+            max_time,
+            codec: self.store.codec.clone(),
+            dict,
+            new_dictionary,
+            key,
+            signing,
+        })
+    }
+
+    /// Append a compressed leaf after the block at `last_block`, together with its new dictionary and any index
+    /// blocks that are due, returning the offset and digest of the last block written.
+    ///
+    /// With `preserve` the staging areas are moved out of the way, otherwise the active one is clobbered. Must be
+    /// called while holding the file lock.
+    fn write_leaf(
+        &mut self, sealed: SealedLeaf, last_block: u64, last_hash: Digest, preserve: bool,
+    ) -> Fallible<(u64, Digest)> {
+        let SealedLeaf {
+            leaf,
+            times,
+            trailer,
+            compressed,
+            key_id,
+            content_hash,
+            leaf_hash,
+            new_dictionary,
+        } = sealed;
         let length = compressed
             .len()
-            .checked_add(LeafHeader::LEN)
+            .checked_add(LeafHeader::LEN + times.len() + trailer.len())
             .and_then(|l| u32::try_from(l).ok())
             .ok_or(Error::numeric_overflow("compression result > 4GiB"))?;
 
+        if let Some((dict_id, bytes, samples)) = new_dictionary {
+            self.write_dictionary(dict_id, &bytes, samples, preserve)?;
+        }
+        if let Some(hash) = leaf_hash {
+            self.last_leaf_hash = Some(hash);
+        }
+
         // must be recorded before appending!
-        let mut current = self.file.end_offset();
+        let mut current = self.store.file.end_offset();
+        let start = current;
 
         // write block header, leaf header, and compressed data at level 0
-        self.file.stream_append(BlockHeader::new(header.last_block, 0, length))?;
-        self.file.stream_append(LeafHeader::new(header.start_idx, header.count))?;
-        self.file.stream_append_bytes(&*compressed)?;
+        self.make_room(BlockHeader::LEN + u32_to_usize(length), preserve)?;
+        let block = BlockHeader::new(last_block, 0, length, 0, key_id, last_hash, content_hash);
+        self.store.file.stream_append(block)?;
+        self.store.file.stream_append(leaf)?;
+        self.store.file.stream_append_bytes(&times)?;
+        self.store.file.stream_append_bytes(&trailer)?;
+        self.store.file.stream_append_bytes(&compressed)?;
+        self.store.file.seal_block(current)?;
 
         // possibly write new index blocks
         let mut level = 1;
@@ -149,55 +920,441 @@ impl EventFile {
             let mut prev_idx = u64::MAX;
             let mut indexes = SmallVec::<[IndexEntry; 16]>::new();
             let mut end_idx = 0;
-            for block in SearchIter::new(&self.file, current) {
+            let (mut min_time, mut max_time) = (u64::MAX, 0);
+            for block in SearchIter::new(&self.store.file, current) {
                 let (offset, block) = block?;
                 if block.level() >= level {
                     prev_idx = offset;
                     break;
                 }
-                let (start_idx, end) = if block.level() == 0 {
-                    let leaf: &LeafHeader = self.file.stream_at(offset + BlockHeader::SIZE)?;
-                    (leaf.start_idx(), leaf.start_idx() + u64::from(leaf.count()))
+                let (start_idx, end, min, max) = if block.level() == 0 {
+                    let leaf: &LeafHeader = self.store.file.stream_at(offset + BlockHeader::SIZE)?;
+                    let end = leaf.start_idx() + u64::from(leaf.count());
+                    (leaf.start_idx(), end, leaf.min_time(), leaf.max_time())
                 } else {
                     let offset = offset + BlockHeader::SIZE;
-                    let branch: &BranchHeader = self.file.stream_at(offset)?;
+                    let branch: &BranchHeader = self.store.file.stream_at(offset)?;
                     let offset = offset + BranchHeader::SIZE;
-                    let index: &IndexEntry = self.file.stream_at(offset)?;
-                    (index.start_idx(), branch.end_idx())
+                    let index: &IndexEntry = self.store.file.stream_at(offset)?;
+                    (index.start_idx(), branch.end_idx(), branch.min_time(), branch.max_time())
                 };
                 if end_idx == 0 {
                     end_idx = end;
                 }
-                indexes.push(IndexEntry::new(offset, start_idx));
+                min_time = min_time.min(min);
+                max_time = max_time.max(max);
+                indexes.push(IndexEntry::new(offset, start_idx, min, max, merkle::block_digest(block)));
             }
             if indexes.len() < 16 {
                 break;
             }
             indexes.reverse();
 
-            let next_current = self.file.end_offset();
             let length = u32::try_from(BranchHeader::LEN + size_of_val(&*indexes)).ctx("index > 4GiB")?;
-            self.file.stream_append(BlockHeader::new(current, level, length))?;
-            self.file.stream_append(BranchHeader::new(prev_idx, end_idx))?;
+            self.make_room(BlockHeader::LEN + u32_to_usize(length), preserve)?;
+            let next_current = self.store.file.end_offset();
+            let prev_hash = merkle::block_digest(self.store.file.stream_at(current)?);
+            let content_hash = merkle::merkle_root(&indexes.iter().map(|e| e.digest()).collect::<Vec<_>>());
+            self.store
+                .file
+                .stream_append(BlockHeader::new(current, level, length, 0, 0, prev_hash, content_hash))?;
+            self.store.file.stream_append(BranchHeader::new(prev_idx, end_idx, min_time, max_time))?;
             let index_bytes =
                 unsafe { slice::from_raw_parts(&*indexes as *const _ as *const u8, size_of_val(&*indexes)) };
-            self.file.stream_append_bytes(index_bytes)?;
+            self.store.file.stream_append_bytes(index_bytes)?;
+            self.store.file.seal_block(next_current)?;
 
             current = next_current;
             level += 1;
         }
+        self.store.file.flush_stream(start, self.store.file.end_offset())?;
+
+        Ok((current, merkle::block_digest(self.store.file.stream_at(current)?)))
+    }
+
+    /// Number of bytes in use by the active staging area.
+    fn staging_used(&self) -> Fallible<usize> {
+        let count = u32_to_usize(self.staging_header()?.count);
+        let layout = self.staging_layout()?;
+        Ok(layout.event_start() + layout.data_len(&self.store.file, Staging::Active, count)?)
+    }
+
+    /// Move the staging areas out of the way of appending `len` bytes to the stream if their events are to be
+    /// preserved.
+    fn make_room(&mut self, len: usize, preserve: bool) -> Fallible<()> {
+        if preserve {
+            let used = self.staging_used()?;
+            self.store.file.make_room(usize_to_u64(len), used)?;
+        }
+        Ok(())
+    }
+
+    /// Append a new dictionary block and make it the latest one.
+    fn write_dictionary(&mut self, dict_id: u32, dictionary: &[u8], samples: u32, preserve: bool) -> Fallible<()> {
+        let header = DictHeader::new(dict_id, samples);
+        let key_id = self.store.current_key_id();
+        let sealed;
+        let bytes = match key_id {
+            0 => dictionary,
+            _ => {
+                sealed = crypt::encrypt(&self.store.key(key_id)?, header.as_slice(), dictionary)?;
+                &sealed
+            }
+        };
+        let offset = self.append_dictionary(self.store.file.dict_offset(), header, key_id, bytes, preserve)?;
+        self.store.file.flush_stream(offset, self.store.file.end_offset())?;
+        self.store.file.set_dict_offset(offset)
+    }
+
+    /// Append a dictionary block chained to the one at `prev`, returning its offset.
+    ///
+    /// CAUTION: this clobbers the staging area unless asked to `preserve` it!
+    fn append_dictionary(
+        &mut self, prev: u64, header: DictHeader, key_id: u32, bytes: &[u8], preserve: bool,
+    ) -> Fallible<u64> {
+        let length = u32::try_from(DictHeader::LEN + bytes.len()).ctx("dictionary > 4GiB")?;
+        self.make_room(BlockHeader::LEN + u32_to_usize(length), preserve)?;
+        let offset = self.store.file.end_offset();
+        let block = BlockHeader::new(prev, DICT_LEVEL, length, 0, key_id, Digest::default(), Digest::default());
+        self.store.file.stream_append(block)?;
+        self.store.file.stream_append(header)?;
+        self.store.file.stream_append_bytes(bytes)?;
+        self.store.file.seal_block(offset)?;
+        Ok(offset)
+    }
 
-        self.prep_staging(current, header.start_idx + u64::from(header.count))?;
+    /// Use the given zstd dictionary for compressing the next and all later blocks, storing it in the file along
+    /// with the next block.
+    ///
+    /// Blocks compressed with earlier dictionaries remain readable.
+    pub fn set_dictionary(&mut self, dictionary: Vec<u8>) {
+        self.pending_dictionary = Some((dictionary, 0));
+    }
 
+    /// Train a new dictionary of at most `max_size` bytes on the events in the given range, see
+    /// [`set_dictionary`](Self::set_dictionary).
+    pub fn train_dictionary(&mut self, range: impl RangeBounds<u64>, max_size: usize) -> Fallible<()> {
+        let samples = self.events(range)?.map(|e| e.map(|e| e.to_vec())).collect::<Fallible<Vec<_>>>()?;
+        let dictionary = codec::train_dictionary(&samples, max_size)?;
+        self.pending_dictionary = Some((dictionary, u32::try_from(samples.len()).unwrap_or(u32::MAX)));
         Ok(())
     }
 
+    /// Id of the latest stored dictionary, counting from 1, or 0 if there is none.
+    pub fn dictionary_id(&self) -> Fallible<u32> {
+        self.store.dictionary_id()
+    }
+
+    /// Hash of the latest leaf before the given staging area, to which the leaf compressed from it is chained.
+    fn stored_last_leaf_hash(&self, header: &StagingHeaderLifted) -> Fallible<LeafHash> {
+        let Some(idx) = header.start_idx.checked_sub(1) else {
+            return Ok(LeafHash::default());
+        };
+        match find_leaf(&self.store.file, header.last_block, idx)? {
+            Some(offset) => {
+                let block: &BlockHeader = self.store.file.stream_at(offset)?;
+                Ok(sign::stored_hash(&leaf_parts(&self.store.file, block)?))
+            }
+            // the latest leaf has been dropped, so there is nothing to chain to
+            None => Ok(LeafHash::default()),
+        }
+    }
+
+    /// Drop the leading blocks that the retention policy no longer requires.
+    fn enforce_retention(&mut self) -> Fallible<()> {
+        if self.retention == Retention::Forever {
+            return Ok(());
+        }
+        let now = unix_millis(SystemTime::now());
+        let next_index = self.next_index()?;
+        let end = self.store.file.end_offset();
+        let mut offset = self.store.file.start_offset();
+        while offset < end {
+            let block: &BlockHeader = self.store.file.stream_at(offset)?;
+            if block.level() == 0 {
+                let leaf: &LeafHeader = self.store.file.stream_after(block)?;
+                let keep = match self.retention {
+                    Retention::Forever => true,
+                    Retention::Events(n) => leaf.start_idx() + u64::from(leaf.count()) + n > next_index,
+                    Retention::Bytes(n) => end - offset <= n,
+                    Retention::Age(age) => {
+                        leaf.sealed().saturating_add(u64::try_from(age.as_millis()).unwrap_or(u64::MAX)) >= now
+                    }
+                };
+                if keep {
+                    break;
+                }
+            }
+            offset += BlockHeader::SIZE + ((u64::from(block.length()) + 7) & !7);
+        }
+        self.drop_before(offset)
+    }
+
+    /// Drop the compressed blocks that only hold events before `idx` and release their disk space, where the
+    /// platform supports punching holes into files.
+    ///
+    /// Only whole blocks are dropped and the staging area is always kept, so some events before `idx` may remain
+    /// readable, see [`first_index`](Self::first_index). Reading dropped events fails with
+    /// [`Error::DataNotPresent`], while ranges with an unbounded start begin at the first retained event.
+    pub fn truncate_before(&mut self, idx: u64) -> Fallible<()> {
+        if idx <= self.first_index()? {
+            return Ok(());
+        }
+        let lock = self.store.lock.clone();
+        let _guard = lock.write();
+        // the sealed staging area lies in the way of relocated dictionaries
+        self.complete_compression()?;
+        let Some(start) = self.truncation_offset(idx)? else {
+            return Ok(());
+        };
+        self.drop_before(start)
+    }
+
+    /// Offset of the leaf holding event `idx`, or the stream end if the event is not yet compressed.
+    fn truncation_offset(&self, idx: u64) -> Fallible<Option<u64>> {
+        let header = self.staging_header()?;
+        if idx >= header.start_idx {
+            Ok(Some(self.store.file.end_offset()))
+        } else {
+            find_leaf(&self.store.file, header.last_block, idx)
+        }
+    }
+
+    /// Drop the blocks before the given stream offset, which must point at a leaf or the stream end, keeping the
+    /// blobs written since the preceding leaf as they belong to retained events.
+    ///
+    /// Dictionaries that are still needed are copied to the stream end first, moving the active staging area out of
+    /// the way, which requires that there be no sealed one. Must be called while holding the file lock.
+    fn drop_before(&mut self, offset: u64) -> Fallible<()> {
+        let offset = self.store.blobs_start(offset)?;
+        if offset <= self.store.file.start_offset() {
+            return Ok(());
+        }
+        let dicts = self.store.dictionaries_to_relocate(offset)?;
+        if !dicts.is_empty() {
+            let start = self.store.file.end_offset();
+            let mut prev = u64::MAX;
+            for dict in dicts {
+                let block: &BlockHeader = self.store.file.stream_at(dict)?;
+                let dict_header: &DictHeader = self.store.file.stream_after(block)?;
+                let length = u32_to_usize(block.length()).saturating_sub(DictHeader::LEN);
+                let bytes = self.store.file.stream_bytes_after(dict_header, length)?.to_vec();
+                let (dict_header, key_id) = (*dict_header, block.key_id());
+                prev = self.append_dictionary(prev, dict_header, key_id, &bytes, true)?;
+            }
+            self.store.file.flush_stream(start, self.store.file.end_offset())?;
+            self.store.file.set_dict_offset(prev)?;
+        }
+        self.store.file.advance_start(offset)
+    }
+
     pub fn flush(&self) -> Fallible<()> {
-        self.file.flush()
+        self.store.file.flush()
+    }
+
+    /// Retrieve a single event by its index, returning `None` if no such event is stored.
+    pub fn get(&self, idx: u64) -> Fallible<Option<EventRef>> {
+        self.store.get(idx)
+    }
+
+    pub fn iter(&self, range: impl RangeBounds<u64>) -> Fallible<RangeIter<'_>> {
+        self.store.iter(range)
+    }
+
+    /// Iterate over the events in the given range together with their indices.
+    pub fn events(&self, range: impl RangeBounds<u64>) -> Fallible<EventIter<'_>> {
+        self.store.events(range)
+    }
+
+    /// Iterate over the given range newest first, see [`RevRangeIter`].
+    pub fn iter_rev(&self, range: impl RangeBounds<u64>) -> Fallible<RevRangeIter<'_>> {
+        self.store.iter_rev(range)
+    }
+
+    /// Iterate over the events in the given range together with their indices, newest first.
+    pub fn events_rev(&self, range: impl RangeBounds<u64>) -> Fallible<RevEventIter<'_>> {
+        self.store.events_rev(range)
+    }
+
+    /// Index of the first retained event whose timestamp is at or after `time`, or the next index if there is
+    /// none; see [`append_with_time`](EventFile::append_with_time).
+    ///
+    /// Only the uncompressed block and index headers are consulted, so this takes logarithmic time.
+    pub fn seek_time(&self, time: u64) -> Fallible<u64> {
+        self.store.seek_time(time)
+    }
+
+    /// Iterate over the events from the first one at or after the start of the time range up to (excluding) the
+    /// first one at or after its end, see [`seek_time`](Self::seek_time).
+    ///
+    /// With non-decreasing timestamps these are exactly the events within the time range.
+    pub fn iter_time(&self, range: impl RangeBounds<u64>) -> Fallible<EventIter<'_>> {
+        self.store.iter_time(range)
+    }
+
+    /// Hash identifying all compressed events, see [`inclusion_proof`](Self::inclusion_proof).
+    ///
+    /// This is the digest of the latest compressed block, which commits to all blocks before it; it is all zeros
+    /// while no block has been written. Events in the staging area are not covered.
+    pub fn root_hash(&self) -> Fallible<Digest> {
+        self.store.root_hash()
+    }
+
+    /// Proof that the compressed event with the given index is covered by the current
+    /// [`root_hash`](Self::root_hash), to be checked with [`verify_inclusion`](EventFile::verify_inclusion).
+    ///
+    /// It holds Merkle paths through the index for the event and for each later top-level block, of which there
+    /// are at most 15 per index level, so its size grows with the square of the logarithm of the number of leaves.
+    pub fn inclusion_proof(&self, idx: u64) -> Fallible<InclusionProof> {
+        self.store.inclusion_proof(idx)
+    }
+
+    /// Check the checksums of all retained blocks and verify that each leaf is signed by the given key and chained
+    /// to its predecessor, see [`EventFileConfig::signing_key`].
+    ///
+    /// Fails with [`Error::InvalidSignature`] for the first leaf that does not pass; events still in the staging
+    /// area are not covered.
+    pub fn verify(&self, public_key: &PublicKey) -> Fallible<()> {
+        sign::verify(&self.store, public_key)
+    }
+
+    pub fn dump_text(&self, lines_per_event: usize, w: impl io::Write) -> io::Result<()> {
+        self.store.dump_text(lines_per_event, w)
+    }
+}
+
+impl Drop for EventFile {
+    fn drop(&mut self) {
+        if let Err(error) = self.finish_compression() {
+            tracing::warn!(%error, "cannot finish background compression");
+        }
+        self.notifier.close();
+    }
+}
+
+/// Read-only access to an event file, obtained from [`EventFile::open_read_only`] or [`EventFile::reader`].
+///
+/// Newly staged events become visible immediately. When the writer has compressed events or otherwise changed the
+/// file since the last [`refresh`](Self::refresh), each read works on a freshly refreshed view of the file, which
+/// calling `refresh` saves. Iterators cover the events stored when they were created. Clones share the mapping and
+/// cache but refresh independently.
+pub struct EventFileReader {
+    store: Store,
+}
+
+impl Clone for EventFileReader {
+    fn clone(&self) -> Self {
+        Self { store: self.store.share() }
+    }
+}
+
+impl EventFileReader {
+    /// Follow the file starting at index `from_idx`, checking for new events every `poll_interval`.
+    pub fn subscribe(self, from_idx: u64, poll_interval: Duration) -> Subscription {
+        Subscription::polling(self, poll_interval, from_idx)
+    }
+
+    /// Re-read the file header and remap the file if it has grown since opening or the last refresh, so that reads
+    /// no longer need to do so by themselves.
+    pub fn refresh(&mut self) -> Fallible<()> {
+        let lock = self.store.lock.clone();
+        let _guard = lock.read();
+        self.store.file.refresh()
+    }
+
+    /// Index that will be assigned to the next event appended by the writer.
+    pub fn next_index(&self) -> Fallible<u64> {
+        let _guard = self.store.reading();
+        self.store.current()?.next_index()
+    }
+
+    /// Index of the oldest event that has not been dropped, see [`EventFile::truncate_before`].
+    pub fn first_index(&self) -> Fallible<u64> {
+        let _guard = self.store.reading();
+        self.store.current()?.first_index()
+    }
+
+    /// Index of the first event that is not yet compressed.
+    pub(crate) fn staged_start_idx(&self) -> Fallible<u64> {
+        let _guard = self.store.reading();
+        self.store.current()?.staged_start_idx()
+    }
+
+    /// Number of events retained in this file, see [`EventFile::len`].
+    pub fn len(&self) -> Fallible<u64> {
+        let _guard = self.store.reading();
+        let store = self.store.current()?;
+        Ok(store.next_index()? - store.first_index()?)
+    }
+
+    pub fn is_empty(&self) -> Fallible<bool> {
+        Ok(self.len()? == 0)
+    }
+
+    /// Retrieve a single event by its index, returning `None` if no such event is stored.
+    pub fn get(&self, idx: u64) -> Fallible<Option<EventRef>> {
+        let _guard = self.store.reading();
+        self.store.current()?.get(idx)
+    }
+
+    pub fn iter(&self, range: impl RangeBounds<u64>) -> Fallible<RangeIter<'_>> {
+        let _guard = self.store.reading();
+        self.store.current()?.iter(range)
+    }
+
+    /// Iterate over the events in the given range together with their indices.
+    pub fn events(&self, range: impl RangeBounds<u64>) -> Fallible<EventIter<'_>> {
+        let _guard = self.store.reading();
+        self.store.current()?.events(range)
     }
 
-    pub fn iter(&self, range: impl RangeBounds<u64>) -> Fallible<RangeIter> {
-        RangeIter::new(self, self.staging_header()?.last_block, range)
+    /// Iterate over the given range newest first, see [`RevRangeIter`].
+    pub fn iter_rev(&self, range: impl RangeBounds<u64>) -> Fallible<RevRangeIter<'_>> {
+        let _guard = self.store.reading();
+        self.store.current()?.iter_rev(range)
+    }
+
+    /// Iterate over the events in the given range together with their indices, newest first.
+    pub fn events_rev(&self, range: impl RangeBounds<u64>) -> Fallible<RevEventIter<'_>> {
+        let _guard = self.store.reading();
+        self.store.current()?.events_rev(range)
+    }
+
+    /// Index of the first retained event at or after `time`, see [`EventFile::seek_time`].
+    pub fn seek_time(&self, time: u64) -> Fallible<u64> {
+        let _guard = self.store.reading();
+        self.store.current()?.seek_time(time)
+    }
+
+    /// Iterate over the events within the given time range, see [`EventFile::iter_time`].
+    pub fn iter_time(&self, range: impl RangeBounds<u64>) -> Fallible<EventIter<'_>> {
+        let _guard = self.store.reading();
+        self.store.current()?.iter_time(range)
+    }
+
+    /// Hash identifying all compressed events, see [`EventFile::root_hash`].
+    pub fn root_hash(&self) -> Fallible<Digest> {
+        let _guard = self.store.reading();
+        self.store.current()?.root_hash()
+    }
+
+    /// Proof that the compressed event with the given index is covered by the current root hash, see
+    /// [`EventFile::inclusion_proof`].
+    pub fn inclusion_proof(&self, idx: u64) -> Fallible<InclusionProof> {
+        let _guard = self.store.reading();
+        self.store.current()?.inclusion_proof(idx)
+    }
+
+    /// Check the checksums and signatures of all retained blocks, see [`EventFile::verify`].
+    pub fn verify(&self, public_key: &PublicKey) -> Fallible<()> {
+        let _guard = self.store.reading();
+        sign::verify(&*self.store.current()?, public_key)
+    }
+
+    pub fn dump_text(&self, lines_per_event: usize, w: impl io::Write) -> io::Result<()> {
+        let _guard = self.store.reading();
+        let store = self.store.current().map_err(io::Error::other)?;
+        store.dump_text(lines_per_event, w)
     }
 }
 
@@ -226,10 +1383,16 @@ embed! {
     isize_to_u64: isize => u64;
 }
 
+/// Milliseconds since the UNIX epoch, saturating at both ends.
+fn unix_millis(time: SystemTime) -> u64 {
+    let millis = time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_millis());
+    u64::try_from(millis).unwrap_or(u64::MAX)
+}
+
 /// TODO:
 ///
 ///  - don’t compress index blocks
-///  - fixed indexing by message number
+///  - fixed indexing by message number (only done for files with a fixed event size)
 ///  - change level 0 index to jump table
 ///  - hand out reference to bytes instead of taking an extractor function
 ///  - clean up low-level access into tiny internal API
@@ -0,0 +1,314 @@
+//! Hash chain and Merkle trees over the compressed blocks, see [`EventFile::root_hash`](crate::EventFile::root_hash).
+//!
+//! Each block header commits to the digest of the block written before it and to a content hash: for a leaf this
+//! covers its index range and the Merkle tree over its events, for a branch the Merkle tree over the digests of
+//! the blocks it references. The digest of the latest block thus identifies all compressed events.
+//!
+//! Proving the inclusion of a single event does not need to follow the chain through every later block: the
+//! branches lead from the event up to its top-level block, and each later top-level block is reached from the one
+//! before it through its first leaf, which is chained to that block, and the branches above that leaf. With
+//! `d` index levels there are at most `15 * d` top-level blocks, so a proof holds `O(d²)` hashes, where `d`
+//! grows logarithmically with the number of leaves.
+//!
+//! Merkle trees are built as described in RFC 6962, with distinct prefixes for the different kinds of nodes.
+
+use crate::{
+    blob::parse_ref,
+    error::Fallible,
+    formats::{BlockHeader, BranchHeader, HasMagic, IndexEntry, IndexEntryLifted, LeafHeader},
+    iter::{decompress, event_bounds, find_leaf, find_top, is_blob, Layout, SearchIter},
+    store::Store,
+    usize_to_u64, Error,
+};
+use sha2::{Digest as _, Sha256};
+use std::fmt;
+
+const EVENT: u8 = 0;
+const NODE: u8 = 1;
+const BLOCK: u8 = 2;
+const LEAF: u8 = 3;
+
+/// SHA-256 hash identifying an event, a block, or the whole log up to some block.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Digest([u8; 32]);
+
+impl Digest {
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+
+    // byte order adapters for use within on-disk structures
+    #[cfg(not(feature = "native"))]
+    pub(crate) fn to_be(self) -> Self {
+        self
+    }
+    #[cfg(not(feature = "native"))]
+    pub(crate) fn from_be(this: Self) -> Self {
+        this
+    }
+
+    fn of(parts: &[&[u8]]) -> Self {
+        let mut hasher = Sha256::new();
+        for part in parts {
+            hasher.update(part);
+        }
+        Self(hasher.finalize().into())
+    }
+}
+
+impl From<[u8; 32]> for Digest {
+    fn from(bytes: [u8; 32]) -> Self {
+        Self(bytes)
+    }
+}
+
+impl fmt::Display for Digest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.iter().try_for_each(|b| write!(f, "{:02x}", b))
+    }
+}
+
+impl fmt::Debug for Digest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Digest({})", self)
+    }
+}
+
+pub(crate) fn event_hash(event: &[u8]) -> Digest {
+    Digest::of(&[&[EVENT], event])
+}
+
+fn node_hash(left: &Digest, right: &Digest) -> Digest {
+    Digest::of(&[&[NODE], &left.0, &right.0])
+}
+
+/// Largest power of two smaller than `n`, which must be at least two.
+fn split(n: usize) -> usize {
+    1 << (usize::BITS - 1 - (n - 1).leading_zeros())
+}
+
+/// Root of the Merkle tree over the given hashes.
+pub(crate) fn merkle_root(hashes: &[Digest]) -> Digest {
+    match hashes.len() {
+        0 => Digest::of(&[]),
+        1 => hashes[0],
+        n => {
+            let k = split(n);
+            node_hash(&merkle_root(&hashes[..k]), &merkle_root(&hashes[k..]))
+        }
+    }
+}
+
+/// Sibling hashes on the way from the hash at position `pos` up to the root, bottom-up.
+fn merkle_path(pos: usize, hashes: &[Digest], path: &mut Vec<Digest>) {
+    let n = hashes.len();
+    if n > 1 {
+        let k = split(n);
+        if pos < k {
+            merkle_path(pos, &hashes[..k], path);
+            path.push(merkle_root(&hashes[k..]));
+        } else {
+            merkle_path(pos - k, &hashes[k..], path);
+            path.push(merkle_root(&hashes[..k]));
+        }
+    }
+}
+
+/// Recompute the root of a tree over `count` hashes from the hash at `pos` and its path.
+fn root_from_path(pos: u64, count: u64, hash: Digest, path: &[Digest]) -> Option<Digest> {
+    if pos >= count {
+        return None;
+    }
+    if count == 1 {
+        return path.is_empty().then_some(hash);
+    }
+    let (sibling, rest) = path.split_last()?;
+    let k = usize_to_u64(split(usize::try_from(count).ok()?));
+    if pos < k {
+        Some(node_hash(&root_from_path(pos, k, hash, rest)?, sibling))
+    } else {
+        Some(node_hash(sibling, &root_from_path(pos - k, count - k, hash, rest)?))
+    }
+}
+
+/// Content hash of a leaf, binding the Merkle root over its events to their indices.
+pub(crate) fn leaf_content(start_idx: u64, count: u32, events_root: &Digest) -> Digest {
+    Digest::of(&[&[LEAF], &start_idx.to_be_bytes(), &count.to_be_bytes(), &events_root.0])
+}
+
+/// Hashes of the events of a decompressed leaf, taken from the references of events stored in blobs.
+pub(crate) fn event_hashes(leaf: &[u8], layout: Layout, count: u32) -> Fallible<Vec<Digest>> {
+    (0..count)
+        .map(|pos| {
+            let (from, to) = event_bounds(leaf, layout, pos);
+            if is_blob(leaf, layout, pos) {
+                Ok(parse_ref(&leaf[from..to])?.1)
+            } else {
+                Ok(event_hash(&leaf[from..to]))
+            }
+        })
+        .collect()
+}
+
+fn digest(prev: &Digest, level: u32, content: &Digest) -> Digest {
+    Digest::of(&[&[BLOCK], &prev.0, &level.to_be_bytes(), &content.0])
+}
+
+/// Digest of a block, committing to all blocks written before it.
+pub(crate) fn block_digest(block: &BlockHeader) -> Digest {
+    digest(&block.prev_hash(), block.level(), &block.content_hash())
+}
+
+/// One step on the way from an event up to the root hash, see [`InclusionProof`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProofStep {
+    /// compute the digest of the leaf holding the event from the event’s Merkle path
+    Leaf {
+        prev_hash: Digest,
+        start_idx: u64,
+        count: u32,
+        path: Vec<Digest>,
+    },
+    /// compute the digest of the branch referencing the current block at `pos` among `count` entries
+    Branch {
+        prev_hash: Digest,
+        level: u32,
+        pos: u64,
+        count: u64,
+        path: Vec<Digest>,
+    },
+    /// compute the digest of the block written after the current one
+    Next { level: u32, content_hash: Digest },
+}
+
+/// Proof that an event is part of the log identified by a root hash, see
+/// [`EventFile::inclusion_proof`](crate::EventFile::inclusion_proof) and
+/// [`verify_inclusion`](crate::EventFile::verify_inclusion).
+///
+/// The steps are public so that proofs can be shipped in any serialisation format.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InclusionProof {
+    pub steps: Vec<ProofStep>,
+}
+
+impl InclusionProof {
+    /// Check that the proof leads from the event with the given index to `root`.
+    pub fn verify(&self, root: &Digest, idx: u64, event: &[u8]) -> bool {
+        let Some((ProofStep::Leaf { prev_hash, start_idx, count, path }, rest)) = self.steps.split_first() else {
+            return false;
+        };
+        let Some(pos) = idx.checked_sub(*start_idx) else {
+            return false;
+        };
+        let Some(events_root) = root_from_path(pos, u64::from(*count), event_hash(event), path) else {
+            return false;
+        };
+        let mut current = digest(prev_hash, 0, &leaf_content(*start_idx, *count, &events_root));
+        for step in rest {
+            current = match step {
+                ProofStep::Leaf { .. } => return false,
+                ProofStep::Branch { prev_hash, level, pos, count, path } => {
+                    let Some(content) = root_from_path(*pos, *count, current, path) else {
+                        return false;
+                    };
+                    digest(prev_hash, *level, &content)
+                }
+                ProofStep::Next { level, content_hash } => digest(&current, *level, content_hash),
+            };
+        }
+        current == *root
+    }
+}
+
+impl Store {
+    /// Digest of the latest compressed block, or all zeros if there is none.
+    pub fn root_hash(&self) -> Fallible<Digest> {
+        Ok(self.staging_header()?.last_hash)
+    }
+
+    pub fn inclusion_proof(&self, idx: u64) -> Fallible<InclusionProof> {
+        let header = self.staging_header()?;
+        let staged = self.staged_start_idx()?;
+        if idx >= staged {
+            return Err(Error::data_not_present("event not yet compressed", idx, staged));
+        }
+        let first = self.first_index()?;
+        if idx < first {
+            return Err(Error::data_not_present("event index before first retained event", idx, first));
+        }
+        let missing = || Error::data_not_present("no block containing event", idx, first);
+        let leaf_offset = find_leaf(&self.file, header.last_block, idx)?.ok_or_else(missing)?;
+        let top = find_top(&self.file, header.last_block, idx)?.ok_or_else(missing)?;
+
+        // descend from there, noting the entry position within each branch
+        let mut branches = Vec::new();
+        let mut offset = top;
+        while offset != leaf_offset {
+            let (step, child) = self.branch_step(offset, |entries| {
+                entries.iter().rposition(|e| e.start_idx <= idx).unwrap_or_default()
+            })?;
+            branches.push(step);
+            offset = child;
+        }
+
+        let block: &BlockHeader = self.file.stream_at(leaf_offset)?;
+        let leaf: &LeafHeader = self.file.stream_after(block)?;
+        let bytes = decompress(self, block, false)?;
+        let hashes = event_hashes(&bytes, Layout::of_leaf(leaf, &bytes), leaf.count())?;
+        let mut path = Vec::new();
+        merkle_path((idx - leaf.start_idx()) as usize, &hashes, &mut path);
+
+        let mut steps = vec![ProofStep::Leaf {
+            prev_hash: block.prev_hash(),
+            start_idx: leaf.start_idx(),
+            count: leaf.count(),
+            path,
+        }];
+        steps.extend(branches.into_iter().rev());
+
+        // then reach each later top-level block through its first leaf, which is chained to the one before
+        let later = SearchIter::new(&self.file, header.last_block)
+            .map(|block| block.map(|(offset, _)| offset))
+            .take_while(|offset| !matches!(offset, Ok(offset) if *offset == top))
+            .collect::<Fallible<Vec<_>>>()?;
+        for &offset in later.iter().rev() {
+            let mut branches = Vec::new();
+            let mut offset = offset;
+            loop {
+                let block: &BlockHeader = self.file.stream_at(offset)?;
+                if block.level() == 0 {
+                    steps.push(ProofStep::Next { level: 0, content_hash: block.content_hash() });
+                    break;
+                }
+                let (step, child) = self.branch_step(offset, |_| 0)?;
+                branches.push(step);
+                offset = child;
+            }
+            steps.extend(branches.into_iter().rev());
+        }
+        Ok(InclusionProof { steps })
+    }
+
+    /// Proof step from the child at the position `pick`ed among the entries of the branch at `offset` up to that
+    /// branch, returned together with the child’s offset.
+    fn branch_step(&self, offset: u64, pick: impl Fn(&[IndexEntryLifted]) -> usize) -> Fallible<(ProofStep, u64)> {
+        let block: &BlockHeader = self.file.stream_at(offset)?;
+        let count = (u64::from(block.length()) - BranchHeader::SIZE) / IndexEntry::SIZE;
+        let entries = offset + BlockHeader::SIZE + BranchHeader::SIZE;
+        let entries = (0..count)
+            .map(|i| self.file.stream_at::<IndexEntry>(entries + i * IndexEntry::SIZE).map(|e| e.lift()))
+            .collect::<Fallible<Vec<_>>>()?;
+        let pos = pick(&entries);
+        let hashes = entries.iter().map(|e| e.digest).collect::<Vec<_>>();
+        let mut path = Vec::new();
+        merkle_path(pos, &hashes, &mut path);
+        let step = ProofStep::Branch {
+            prev_hash: block.prev_hash(),
+            level: block.level(),
+            pos: usize_to_u64(pos),
+            count,
+            path,
+        };
+        Ok((step, entries[pos].offset))
+    }
+}
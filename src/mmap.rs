@@ -1,31 +1,112 @@
 use crate::{
     error::{ErrCtx, Fallible},
-    formats::{HasMagic, MmapFileHeader},
+    formats::{BlockHeader, HasMagic, MmapFileHeader},
     isize_to_u64, usize_to_u64, Error,
 };
-use memmap2::{MmapMut, MmapOptions};
+use memmap2::{Mmap, MmapOptions, MmapRaw};
 use std::{
     fs::{metadata, File},
+    io,
     mem::{align_of, size_of},
+    ops::Deref,
     path::PathBuf,
-    slice,
+    ptr, slice,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
 };
 
+/// version of the on-disk format written by this library
+const STREAM_VERSION: u32 = 15;
+
+/// One of the two staging areas of an [`MmapFile`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Staging {
+    /// the area receiving appended events
+    Active,
+    /// the full area whose events are being compressed in the background
+    Sealed,
+}
+
+/// Memory mapping of an [`MmapFile`], writable unless the file was opened read-only.
+///
+/// Mappings are reference counted so that readers can share the writer’s mapping; an old mapping stays valid
+/// for its remaining holders when the writer remaps the grown file. Readers on other threads only look at a shared
+/// mapping while holding the [`FileLock`](crate::store::FileLock) that the writer holds exclusively while writing,
+/// and writes go through raw pointers so that no mutable slice of the whole mapping is ever created.
+#[derive(Clone)]
+enum Mapping {
+    ReadWrite(Arc<MmapRaw>),
+    /// the writer’s mapping as seen by a reader
+    Shared(Arc<MmapRaw>),
+    ReadOnly(Arc<Mmap>),
+}
+
+impl Mapping {
+    fn writable(&mut self) -> Fallible<*mut u8> {
+        match self {
+            Mapping::ReadWrite(mmap) => Ok(mmap.as_mut_ptr()),
+            Mapping::Shared(_) | Mapping::ReadOnly(_) => Err(Error::read_only()),
+        }
+    }
+
+    fn flush_range(&self, offset: usize, len: usize) -> io::Result<()> {
+        match self {
+            Mapping::ReadWrite(mmap) => mmap.flush_range(offset, len),
+            Mapping::Shared(_) | Mapping::ReadOnly(_) => Ok(()),
+        }
+    }
+
+    /// The same mapping for use by a reader.
+    fn view(&self) -> Self {
+        match self {
+            Mapping::ReadWrite(mmap) | Mapping::Shared(mmap) => Mapping::Shared(mmap.clone()),
+            Mapping::ReadOnly(mmap) => Mapping::ReadOnly(mmap.clone()),
+        }
+    }
+}
+
+impl Deref for Mapping {
+    type Target = [u8];
+
+    fn deref(&self) -> &Self::Target {
+        match self {
+            Mapping::ReadWrite(mmap) | Mapping::Shared(mmap) => unsafe {
+                slice::from_raw_parts(mmap.as_ptr(), mmap.len())
+            },
+            Mapping::ReadOnly(mmap) => mmap,
+        }
+    }
+}
+
 /// A file that contains:
 ///  - 4kiB header
-///  - bytes named [start_offset..end_offset] (boundaries 8-byte aligned)
-///  - unnamed bytes until the file end
+///  - bytes named [base_offset..end_offset] (boundaries 8-byte aligned), of which those before start_offset
+///    have been dropped
+///  - unnamed bytes, among them possibly a sealed staging area from sealed_offset up to staging_offset
+///  - the active staging area from staging_offset until the file end
+///
+/// While a staging area is sealed, blocks appended to the stream (i.e. its leaf) are only published in the header
+/// together with clearing sealed_offset, so that a crash leaves the sealed events to be compressed again.
 pub struct MmapFile {
     path: PathBuf,
-    file: File,
-    mmap: MmapMut,
+    file: Arc<File>,
+    mmap: Mapping,
+    /// most recent mapping of the file, shared between the writer and its readers
+    latest: Arc<Mutex<Mapping>>,
     start_offset: u64,
     end_offset: u64,
+    base_offset: u64,
+    dict_offset: u64,
+    staging_offset: u64,
+    sealed_offset: u64,
+    event_size: u32,
+    leaf_events: u32,
 }
 
 impl MmapFile {
-    pub fn new(path: PathBuf, user_version: u32) -> Fallible<Self> {
-        let file = File::options().create(true).read(true).write(true).open(&*path).ctx(&*path)?;
+    /// Open the file, creating it if it does not exist yet; a new file records the given fixed event size and
+    /// number of events per leaf, see [`fixed_layout`](Self::fixed_layout).
+    pub fn new(path: PathBuf, user_version: u32, fixed_layout: Option<(u32, u32)>) -> Fallible<Self> {
+        let file = File::options().create(true).truncate(false).read(true).write(true).open(&*path).ctx(&*path)?;
         let len = metadata(&path).ctx(&*path)?.len();
         if len < 4096 {
             if len > 0 {
@@ -33,44 +114,324 @@ impl MmapFile {
             }
             file.set_len(4096).ctx(&*path)?;
         }
-        let mmap = unsafe { MmapOptions::new().map_mut(&file) }.ctx(&*path)?;
-        let mut ret = Self { path, file, mmap, start_offset: 0, end_offset: 0 };
+        let mmap = Mapping::ReadWrite(Arc::new(MmapOptions::new().map_raw(&file).ctx(&*path)?));
+        let mut ret = Self::with_mapping(path, file, mmap);
         if len < 4096 {
             // we created the file
-            ret.put(0, MmapFileHeader::new(1, user_version, 0, 0))?;
-            ret.mmap.flush().ctx(&*ret.path)?;
+            let (event_size, leaf_events) = fixed_layout.unwrap_or_default();
+            ret.put(
+                0,
+                MmapFileHeader::new(
+                    STREAM_VERSION,
+                    user_version,
+                    0,
+                    0,
+                    0,
+                    u64::MAX,
+                    0,
+                    u64::MAX,
+                    event_size,
+                    leaf_events,
+                ),
+            )?;
+            ret.event_size = event_size;
+            ret.leaf_events = leaf_events;
+            ret.flush()?;
         } else {
-            let header = *ret.at::<MmapFileHeader>(0)?;
-            if header.stream_version() != 1 {
-                return Err(Error::wrong_stream_version(header.stream_version()));
-            }
-            if header.user_version() != user_version {
-                return Err(Error::wrong_user_version(user_version, header.user_version()));
-            }
-            ret.start_offset = header.start_offset();
-            ret.end_offset = header.end_offset();
+            ret.check_header(user_version)?;
+        }
+        Ok(ret)
+    }
+
+    pub fn open_read_only(path: PathBuf, user_version: u32) -> Fallible<Self> {
+        let file = File::options().read(true).open(&*path).ctx(&*path)?;
+        let len = metadata(&path).ctx(&*path)?.len();
+        if len < 4096 {
+            return Err(Error::data_corruption("file is too small", len, 4096));
         }
+        let mmap = Mapping::ReadOnly(Arc::new(unsafe { MmapOptions::new().map(&file) }.ctx(&*path)?));
+        let mut ret = Self::with_mapping(path, file, mmap);
+        ret.check_header(user_version)?;
         Ok(ret)
     }
 
+    fn with_mapping(path: PathBuf, file: File, mmap: Mapping) -> Self {
+        let latest = Arc::new(Mutex::new(mmap.clone()));
+        Self {
+            path,
+            file: Arc::new(file),
+            mmap,
+            latest,
+            start_offset: 0,
+            base_offset: 0,
+            end_offset: 0,
+            dict_offset: u64::MAX,
+            staging_offset: 0,
+            sealed_offset: u64::MAX,
+            event_size: 0,
+            leaf_events: 0,
+        }
+    }
+
+    /// A read-only view of this file that shares its mapping and follows it when the file grows.
+    pub fn share(&self) -> Self {
+        Self {
+            path: self.path.clone(),
+            file: self.file.clone(),
+            mmap: self.mmap.view(),
+            latest: self.latest.clone(),
+            start_offset: self.start_offset,
+            base_offset: self.base_offset,
+            end_offset: self.end_offset,
+            dict_offset: self.dict_offset,
+            staging_offset: self.staging_offset,
+            sealed_offset: self.sealed_offset,
+            event_size: self.event_size,
+            leaf_events: self.leaf_events,
+        }
+    }
+
+    fn latest(&self) -> MutexGuard<'_, Mapping> {
+        self.latest.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn check_header(&mut self, user_version: u32) -> Fallible<()> {
+        let header = *self.at::<MmapFileHeader>(0)?;
+        if header.stream_version() != STREAM_VERSION {
+            return Err(Error::wrong_stream_version(header.stream_version()));
+        }
+        if header.user_version() != user_version {
+            return Err(Error::wrong_user_version(user_version, header.user_version()));
+        }
+        self.start_offset = header.start_offset();
+        self.base_offset = header.base_offset();
+        self.end_offset = header.end_offset();
+        self.dict_offset = header.dict_offset();
+        self.staging_offset = header.staging_offset();
+        self.sealed_offset = header.sealed_offset();
+        self.event_size = header.event_size();
+        self.leaf_events = header.leaf_events();
+        Ok(())
+    }
+
+    /// Pick up changes made by the writer: switch to its latest mapping or remap the file if its size has
+    /// changed, then re-read the header.
+    pub fn refresh(&mut self) -> Fallible<()> {
+        let mut latest = self.latest();
+        if let Mapping::ReadOnly(mmap) = &*latest {
+            // the writer is in another process
+            let len = self.file.metadata().ctx(&*self.path)?.len();
+            if len != usize_to_u64(mmap.len()) {
+                *latest = Mapping::ReadOnly(Arc::new(unsafe { Mmap::map(&*self.file) }.ctx(&*self.path)?));
+            }
+        }
+        let mmap = latest.view();
+        drop(latest);
+        self.mmap = mmap;
+        let header = self.header()?.lift();
+        self.start_offset = header.start_offset;
+        self.base_offset = header.base_offset;
+        self.end_offset = header.end_offset;
+        self.dict_offset = header.dict_offset;
+        self.staging_offset = header.staging_offset;
+        self.sealed_offset = header.sealed_offset;
+        Ok(())
+    }
+
+    /// Whether the writer has changed the file since the last [`refresh`](Self::refresh), i.e. whether it has
+    /// compressed events, moved a staging area, dropped blocks, or grown the file.
+    pub fn is_stale(&self) -> Fallible<bool> {
+        let header = self.header()?.lift();
+        if header.start_offset != self.start_offset
+            || header.end_offset != self.end_offset
+            || header.base_offset != self.base_offset
+            || header.dict_offset != self.dict_offset
+            || header.staging_offset != self.staging_offset
+            || header.sealed_offset != self.sealed_offset
+        {
+            return Ok(true);
+        }
+        match &self.mmap {
+            Mapping::ReadWrite(_) => Ok(false),
+            Mapping::Shared(mmap) => match &*self.latest() {
+                Mapping::ReadWrite(latest) => Ok(!Arc::ptr_eq(mmap, latest)),
+                _ => Ok(false),
+            },
+            Mapping::ReadOnly(mmap) => Ok(self.file.metadata().ctx(&*self.path)?.len() != usize_to_u64(mmap.len())),
+        }
+    }
+
     pub fn header(&self) -> Fallible<&MmapFileHeader> {
         self.at(0)
     }
 
     pub fn flush(&self) -> Fallible<()> {
-        Ok(self.mmap.flush().ctx("flushing")?)
+        Ok(self.mmap.flush_range(0, self.mmap.len()).ctx("flushing")?)
+    }
+
+    pub fn flush_header(&self) -> Fallible<()> {
+        Ok(self.mmap.flush_range(0, MmapFileHeader::LEN).ctx("flushing header")?)
+    }
+
+    pub fn flush_stream(&self, from: u64, to: u64) -> Fallible<()> {
+        let len = self.stream_bytes(from, to)?.len();
+        let start = 4096 + (from - self.base_offset) as usize;
+        Ok(self.mmap.flush_range(start, len).ctx("flushing stream")?)
+    }
+
+    pub fn flush_staging(&self, from: usize, to: usize) -> Fallible<()> {
+        let len = self.staging_bytes(from, to)?.len();
+        Ok(self.mmap.flush_range(self.staging_start() + from, len).ctx("flushing staging area")?)
+    }
+
+    pub fn start_offset(&self) -> u64 {
+        self.start_offset
     }
 
     pub fn end_offset(&self) -> u64 {
         self.end_offset
     }
 
+    /// Stream offset of the latest dictionary block, `u64::MAX` if there is none.
+    pub fn dict_offset(&self) -> u64 {
+        self.dict_offset
+    }
+
+    /// Point the header at a new latest dictionary block, which must already be flushed; while a staging area is
+    /// sealed this is deferred to [`clear_sealed`](Self::clear_sealed).
+    pub fn set_dict_offset(&mut self, offset: u64) -> Fallible<()> {
+        self.dict_offset = offset;
+        if self.sealed_offset().is_none() {
+            self.at_mut::<MmapFileHeader>(0)?.set_dict_offset(offset);
+            self.flush_header()?;
+        }
+        Ok(())
+    }
+
+    /// Size of every event and number of events in every leaf, if the file was created with a fixed event size.
+    pub fn fixed_layout(&self) -> Option<(u32, u32)> {
+        (self.event_size != 0).then_some((self.event_size, self.leaf_events))
+    }
+
+    /// Stream offset of the sealed staging area, if there is one.
+    pub fn sealed_offset(&self) -> Option<u64> {
+        (self.sealed_offset != u64::MAX).then_some(self.sealed_offset)
+    }
+
+    pub fn staging_offset(&self) -> u64 {
+        self.staging_offset
+    }
+
+    /// Seal the active staging area and start a new one at the given stream offset behind it, see
+    /// [`publish_staging`](Self::publish_staging).
+    pub fn seal_staging(&mut self, offset: u64) {
+        self.sealed_offset = self.staging_offset;
+        self.staging_offset = offset;
+    }
+
+    /// Forget the sealed staging area once its events have been compressed, publishing the stream end and latest
+    /// dictionary; the blocks appended since sealing must already be flushed.
+    pub fn clear_sealed(&mut self) -> Fallible<()> {
+        let (end, dict) = (self.end_offset, self.dict_offset);
+        let header = self.at_mut::<MmapFileHeader>(0)?;
+        header.set_end_offset(end);
+        header.set_dict_offset(dict);
+        header.set_sealed_offset(u64::MAX);
+        self.flush_header()?;
+        self.sealed_offset = u64::MAX;
+        Ok(())
+    }
+
+    /// Reset the active staging area to start right at the stream end, see
+    /// [`publish_staging`](Self::publish_staging).
+    pub fn reset_staging(&mut self) {
+        self.staging_offset = self.end_offset;
+    }
+
+    /// Point the header at the staging areas chosen by [`seal_staging`](Self::seal_staging) or
+    /// [`reset_staging`](Self::reset_staging) once the new active area has been prepared and flushed.
+    pub fn publish_staging(&mut self) -> Fallible<()> {
+        let (staging, sealed) = (self.staging_offset, self.sealed_offset);
+        let header = self.at_mut::<MmapFileHeader>(0)?;
+        header.set_staging_offset(staging);
+        header.set_sealed_offset(sealed);
+        self.flush_header()
+    }
+
+    /// Move the active staging area, of which the first `used` bytes are in use, out of the way if appending
+    /// `len` bytes to the stream would reach into it; a sealed staging area in front of it is moved along.
+    pub fn make_room(&mut self, len: u64, used: usize) -> Fallible<()> {
+        let end = self.end_offset + ((len + 7) & !7);
+        let from = self.sealed_offset().unwrap_or(self.staging_offset);
+        if end <= from {
+            return Ok(());
+        }
+        let target = end.max(self.staging_offset + ((usize_to_u64(used) + 7) & !7));
+        if self.sealed_offset().is_some() {
+            self.move_sealed(target, used)
+        } else {
+            self.move_staging(target, used)
+        }
+    }
+
+    /// Move the active staging area, of which the first `used` bytes are in use, back down to the stream end if it
+    /// fits into the gap, so that the file does not keep growing ahead of the stream.
+    pub fn close_gap(&mut self, used: usize) -> Fallible<()> {
+        let end = self.end_offset + ((usize_to_u64(used) + 7) & !7);
+        if self.sealed_offset().is_none() && end <= self.staging_offset {
+            self.move_staging(self.end_offset, used)?;
+        }
+        Ok(())
+    }
+
+    /// Move the sealed staging area together with the active one behind it to `target`, copying both before the
+    /// header is pointed at the copy.
+    fn move_sealed(&mut self, target: u64, used: usize) -> Fallible<()> {
+        let (start, _) = self.area(Staging::Sealed)?;
+        let len = self.staging_start() - start + used;
+        let bytes = self.mmap[start..start + len].to_vec();
+        let staging = target + (self.staging_offset - self.sealed_offset);
+        (self.sealed_offset, self.staging_offset) = (target, staging);
+        self.ensure_staging_len(used)?;
+        let (start, _) = self.area(Staging::Sealed)?;
+        self.write(start, &bytes)?;
+        self.mmap.flush_range(start, len).ctx("flushing staging areas")?;
+        let header = self.at_mut::<MmapFileHeader>(0)?;
+        header.set_sealed_offset(target);
+        header.set_staging_offset(staging);
+        self.flush_header()
+    }
+
+    fn move_staging(&mut self, target: u64, used: usize) -> Fallible<()> {
+        let bytes = self.staging_bytes(0, used)?.to_vec();
+        self.staging_offset = target;
+        self.ensure_staging_len(used)?;
+        self.write(self.staging_start(), &bytes)?;
+        self.flush_staging(0, used)?;
+        self.at_mut::<MmapFileHeader>(0)?.set_staging_offset(target);
+        self.flush_header()
+    }
+
+    /// File position and length of the given staging area.
+    fn area(&self, area: Staging) -> Fallible<(usize, usize)> {
+        match area {
+            Staging::Active => Ok((self.staging_start(), self.staging_len())),
+            Staging::Sealed => match self.sealed_offset() {
+                Some(offset) => {
+                    let start = 4096 + (offset - self.base_offset) as usize;
+                    Ok((start, self.staging_start().saturating_sub(start)))
+                }
+                None => Err(Error::data_not_present("no sealed staging area", 0, self.end_offset)),
+            },
+        }
+    }
+
     pub fn staging_len(&self) -> usize {
-        self.mmap.len() - self.staging_start()
+        self.mmap.len().saturating_sub(self.staging_start())
     }
 
     pub fn staging_start(&self) -> usize {
-        4096 + (self.end_offset - self.start_offset) as usize
+        4096 + (self.staging_offset - self.base_offset) as usize
     }
 
     fn validate_range<T: HasMagic>(&self, offset: usize) -> Fallible<()> {
@@ -109,13 +470,16 @@ impl MmapFile {
                 u64::from_be_bytes(T::MAGIC.try_into().unwrap_or([0; 8])),
             ));
         }
-        Ok(unsafe { &mut *(self.mmap.as_ptr().add(offset + T::MAGIC.len()) as *mut T) })
+        Ok(unsafe { &mut *(self.mmap.writable()?.add(offset + T::MAGIC.len()) as *mut T) })
     }
 
     fn put<T: HasMagic>(&mut self, offset: usize, value: T) -> Fallible<()> {
         self.validate_range::<T>(offset)?;
-        self.mmap[offset..offset + T::MAGIC.len()].copy_from_slice(T::MAGIC);
-        unsafe { std::ptr::write(self.mmap.as_ptr().add(offset + T::MAGIC.len()) as *mut T, value) };
+        let mmap = self.mmap.writable()?;
+        unsafe {
+            ptr::copy_nonoverlapping(T::MAGIC.as_ptr(), mmap.add(offset), T::MAGIC.len());
+            ptr::write(mmap.add(offset + T::MAGIC.len()) as *mut T, value);
+        }
         Ok(())
     }
 
@@ -128,7 +492,8 @@ impl MmapFile {
                 usize_to_u64(self.mmap.len()),
             ));
         }
-        unsafe { slice::from_raw_parts_mut(self.mmap.as_mut_ptr().add(offset), bytes.len()) }.copy_from_slice(bytes);
+        let mmap = self.mmap.writable()?;
+        unsafe { ptr::copy_nonoverlapping(bytes.as_ptr(), mmap.add(offset), bytes.len()) };
         Ok(())
     }
 
@@ -144,11 +509,51 @@ impl MmapFile {
                 self.end_offset,
             ));
         }
-        self.at((offset - self.start_offset + 4096) as usize)
+        self.at((offset - self.base_offset + 4096) as usize)
     }
 
+    pub fn stream_at_mut<T: HasMagic>(&mut self, offset: u64) -> Fallible<&mut T> {
+        if offset < self.start_offset {
+            return Err(Error::data_not_present("index before start offset", offset, self.start_offset));
+        }
+        let end = offset + T::SIZE;
+        if end > self.end_offset {
+            return Err(Error::data_not_present(
+                "object reaching beyond end offset",
+                offset,
+                self.end_offset,
+            ));
+        }
+        self.at_mut((offset - self.base_offset + 4096) as usize)
+    }
+
+    /// Compute the checksum over the payload of the block at the given stream offset.
+    pub fn block_checksum(&self, offset: u64) -> Fallible<u32> {
+        let block: &BlockHeader = self.stream_at(offset)?;
+        let start = offset + BlockHeader::SIZE;
+        let bytes = self.stream_bytes(start, start + u64::from(block.length()))?;
+        Ok(crc32fast::hash(bytes))
+    }
+
+    /// Record the checksum of a fully written block in its header.
+    pub fn seal_block(&mut self, offset: u64) -> Fallible<()> {
+        let checksum = self.block_checksum(offset)?;
+        self.stream_at_mut::<BlockHeader>(offset)?.set_checksum(checksum);
+        Ok(())
+    }
+
+    pub fn verify_block(&self, offset: u64) -> Fallible<()> {
+        let expected = self.stream_at::<BlockHeader>(offset)?.checksum();
+        let found = self.block_checksum(offset)?;
+        if found != expected {
+            return Err(Error::checksum_mismatch(offset, found, expected));
+        }
+        Ok(())
+    }
+
+    /// Stream offset of the given object, pointing at its magic value (i.e. suitable for [`Self::stream_at`]).
     pub fn stream_offset<T: HasMagic>(&self, at: &T) -> Fallible<u64> {
-        let off = unsafe { (at as *const T as *const u8).offset_from(self.mmap.as_ptr()) };
+        let off = unsafe { (at as *const T as *const u8).offset_from(self.mmap.as_ptr()) } - T::MAGIC.len() as isize;
         if off < 4096 {
             return Err(Error::data_corruption("invalid reference", isize_to_u64(off), 4096));
         }
@@ -159,7 +564,7 @@ impl MmapFile {
                 usize_to_u64(self.staging_start()),
             ));
         }
-        Ok(off as u64 - 4096 + self.start_offset)
+        Ok(off as u64 - 4096 + self.base_offset)
     }
 
     pub fn stream_after<T: HasMagic, U: HasMagic>(&self, at: &T) -> Fallible<&U> {
@@ -184,7 +589,7 @@ impl MmapFile {
         }
         Ok(unsafe {
             slice::from_raw_parts(
-                self.mmap.as_ptr().add(4096 + (from - self.start_offset) as usize),
+                self.mmap.as_ptr().add(4096 + (from - self.base_offset) as usize),
                 (to - from) as usize,
             )
         })
@@ -192,47 +597,82 @@ impl MmapFile {
 
     pub fn stream_bytes_after<T: HasMagic>(&self, at: &T, length: usize) -> Fallible<&[u8]> {
         let start = unsafe { (at as *const T as *const u8).offset_from(self.mmap.as_ptr()) } as u64 - 4096
-            + self.start_offset
+            + self.base_offset
             + usize_to_u64(size_of::<T>());
         let end = start + usize_to_u64(length);
         self.stream_bytes(start, end)
     }
 
-    /// CAUTION: this clobbers the staging area!
+    /// CAUTION: this clobbers the staging area if it is in the way, see [`Self::make_room`]!
     pub fn stream_append<T: HasMagic>(&mut self, value: T) -> Fallible<()> {
-        self.ensure_staging_len(T::LEN)?;
-        self.put(self.staging_start(), value)?;
-        self.end_offset += T::SIZE;
-        let end = self.end_offset;
-        self.at_mut::<MmapFileHeader>(0)?.set_end_offset(end);
-        Ok(())
+        let pos = 4096 + (self.end_offset - self.base_offset) as usize;
+        self.advance_end(T::SIZE)?;
+        self.put(pos, value)
     }
 
-    /// CAUTION: this clobbers the staging area!
+    /// CAUTION: this clobbers the staging area if it is in the way, see [`Self::make_room`]!
     pub fn stream_append_bytes(&mut self, bytes: &[u8]) -> Fallible<()> {
-        self.ensure_staging_len(bytes.len())?;
-        self.write(self.staging_start(), bytes)?;
-        self.end_offset += usize_to_u64(bytes.len() + 7) & !7;
-        let end = self.end_offset;
-        self.at_mut::<MmapFileHeader>(0)?.set_end_offset(end);
+        let pos = 4096 + (self.end_offset - self.base_offset) as usize;
+        self.advance_end(usize_to_u64(bytes.len() + 7) & !7)?;
+        self.write(pos, bytes)
+    }
+
+    /// Extend the stream by `len` bytes, pushing the active staging area ahead of it as needed.
+    fn advance_end(&mut self, len: u64) -> Fallible<()> {
+        let end = self.end_offset + len;
+        if end > self.staging_offset {
+            self.staging_offset = end;
+            self.at_mut::<MmapFileHeader>(0)?.set_staging_offset(end);
+        }
+        self.ensure_staging_len(0)?;
+        self.end_offset = end;
+        // published by `clear_sealed` otherwise
+        if self.sealed_offset().is_none() {
+            self.at_mut::<MmapFileHeader>(0)?.set_end_offset(end);
+        }
+        Ok(())
+    }
+
+    /// Drop the stream bytes before `offset`, releasing their disk space where the platform supports it.
+    pub fn advance_start(&mut self, offset: u64) -> Fallible<()> {
+        if offset < self.start_offset || offset > self.end_offset {
+            return Err(Error::data_not_present(
+                "new start offset outside of stream",
+                offset,
+                self.end_offset,
+            ));
+        }
+        // persist the new start before the data vanish
+        self.at_mut::<MmapFileHeader>(0)?.set_start_offset(offset);
+        self.flush_header()?;
+        self.start_offset = offset;
+        punch_hole(&self.file, 4096, offset - self.base_offset).ctx(&*self.path)?;
         Ok(())
     }
 
-    pub fn clear_staging(&mut self) {
+    pub fn clear_staging(&mut self) -> Fallible<()> {
         let start = self.staging_start();
-        self.mmap[start..].fill(0);
+        let len = self.staging_len();
+        let mmap = self.mmap.writable()?;
+        unsafe { ptr::write_bytes(mmap.add(start), 0, len) };
+        Ok(())
     }
 
     pub fn staging_at<T: HasMagic>(&self, offset: usize) -> Fallible<&T> {
+        self.area_at(Staging::Active, offset)
+    }
+
+    pub fn area_at<T: HasMagic>(&self, area: Staging, offset: usize) -> Fallible<&T> {
+        let (start, len) = self.area(area)?;
         let end = offset + T::LEN;
-        if end > self.staging_len() {
+        if end > len {
             return Err(Error::data_corruption(
                 "index beyond staging end",
                 usize_to_u64(end),
-                usize_to_u64(self.staging_len()),
+                usize_to_u64(len),
             ));
         }
-        self.at(offset + self.staging_start())
+        self.at(offset + start)
     }
 
     pub fn staging_at_mut<T: HasMagic>(&mut self, offset: usize) -> Fallible<&mut T> {
@@ -248,26 +688,38 @@ impl MmapFile {
     }
 
     pub fn staging_bytes(&self, from: usize, to: usize) -> Fallible<&[u8]> {
-        if to > self.staging_len() {
+        self.area_bytes(Staging::Active, from, to)
+    }
+
+    pub fn area_bytes(&self, area: Staging, from: usize, to: usize) -> Fallible<&[u8]> {
+        let (start, len) = self.area(area)?;
+        if to > len {
             return Err(Error::data_corruption(
                 "byte index beyond staging end",
                 usize_to_u64(to),
-                usize_to_u64(self.staging_len()),
+                usize_to_u64(len),
             ));
         }
         if from > to {
             return Err(Error::numeric_overflow("negative range of staging_bytes requested"));
         }
-        Ok(unsafe { slice::from_raw_parts(self.mmap.as_ptr().add(self.staging_start() + from), to - from) })
+        Ok(unsafe { slice::from_raw_parts(self.mmap.as_ptr().add(start + from), to - from) })
+    }
+
+    /// Length of the given staging area, which for the active one extends up to the file end.
+    pub fn area_len(&self, area: Staging) -> Fallible<usize> {
+        Ok(self.area(area)?.1)
     }
 
     pub fn ensure_staging_len(&mut self, len: usize) -> Fallible<()> {
-        if self.staging_len() >= len {
+        if self.mmap.len() >= self.staging_start() + len {
             return Ok(());
         }
-        let file_size = 4096 + self.end_offset - self.start_offset + usize_to_u64(len);
+        let file_size = 4096 + self.staging_offset - self.base_offset + usize_to_u64(len);
         self.file.set_len(file_size).ctx(&*self.path)?;
-        self.mmap = unsafe { MmapMut::map_mut(&self.file) }.ctx(&*self.path)?;
+        self.mmap.writable()?;
+        self.mmap = Mapping::ReadWrite(Arc::new(MmapRaw::map_raw(&*self.file).ctx(&*self.path)?));
+        *self.latest() = self.mmap.clone();
         Ok(())
     }
 
@@ -280,3 +732,28 @@ impl MmapFile {
         self.write(self.staging_start() + offset, bytes)
     }
 }
+
+/// Deallocate the given byte range of the file, which afterwards reads as zeros.
+#[cfg(target_os = "linux")]
+fn punch_hole(file: &File, offset: u64, len: u64) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    if len == 0 {
+        return Ok(());
+    }
+    let (offset, len) = (offset as libc::off_t, len as libc::off_t);
+    let mode = libc::FALLOC_FL_PUNCH_HOLE | libc::FALLOC_FL_KEEP_SIZE;
+    if unsafe { libc::fallocate(file.as_raw_fd(), mode, offset, len) } < 0 {
+        let err = io::Error::last_os_error();
+        // not all file systems support this, in which case the space is just not reclaimed
+        if err.raw_os_error() != Some(libc::EOPNOTSUPP) {
+            return Err(err);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn punch_hole(_file: &File, _offset: u64, _len: u64) -> io::Result<()> {
+    Ok(())
+}
@@ -0,0 +1,98 @@
+//! Decompression of upcoming leaves on worker threads, see [`RangeIter::read_ahead`](crate::RangeIter::read_ahead).
+
+use crate::{error::Fallible, iter::decompress, store::Store, Error};
+use std::{
+    collections::VecDeque,
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex, OnceLock, PoisonError, Weak,
+    },
+    thread,
+};
+
+/// Offset of a leaf, first event index to deliver from it, and its decompressed contents.
+pub(crate) type Leaf = (u64, u64, Arc<[u8]>);
+
+type Reply = Receiver<Fallible<Arc<[u8]>>>;
+type Task = Box<dyn FnOnce() + Send>;
+
+/// Queue of the worker threads shared by all iterators, as many as there are cores, started on first use.
+fn pool() -> &'static Mutex<Sender<Task>> {
+    static POOL: OnceLock<Mutex<Sender<Task>>> = OnceLock::new();
+    POOL.get_or_init(|| {
+        let (tasks, rx) = mpsc::channel::<Task>();
+        let rx = Arc::new(Mutex::new(rx));
+        for _ in 0..thread::available_parallelism().map_or(1, |n| n.get()) {
+            let rx = rx.clone();
+            thread::spawn(move || loop {
+                let task = rx.lock().unwrap_or_else(PoisonError::into_inner).recv();
+                let Ok(task) = task else {
+                    return;
+                };
+                // a panicking task drops its reply, which the iterator reports as cancelled
+                panic::catch_unwind(AssertUnwindSafe(task)).ok();
+            });
+        }
+        Mutex::new(tasks)
+    })
+}
+
+/// Leaves decompressed into the cache by the shared worker threads, with the leaves requested so far in order.
+pub(crate) struct ReadAhead {
+    depth: usize,
+    /// view of the file for the workers, which skip the leaves of dropped iterators
+    store: Arc<Store>,
+    /// leaf offset, first event index to deliver, and pending result
+    queue: VecDeque<Fallible<(u64, u64, Reply)>>,
+}
+
+impl ReadAhead {
+    /// Keep up to `depth` leaves in flight.
+    pub fn new(store: &Store, depth: usize) -> Self {
+        Self {
+            depth,
+            store: Arc::new(store.share()),
+            queue: VecDeque::new(),
+        }
+    }
+
+    pub fn has_room(&self) -> bool {
+        self.queue.len() < self.depth
+    }
+
+    /// Start decompressing the leaf at the given offset.
+    pub fn submit(&mut self, offset: u64, start_idx: u64) {
+        let (reply, rx) = mpsc::sync_channel(1);
+        let store = Arc::downgrade(&self.store);
+        let task = Box::new(move || decompress_leaf(&store, offset, reply));
+        let sent = pool().lock().unwrap_or_else(PoisonError::into_inner).send(task).is_ok();
+        self.queue.push_back(if sent { Ok((offset, start_idx, rx)) } else { Err(Error::cancelled()) });
+    }
+
+    /// Deliver an error in order, after the leaves submitted before it.
+    pub fn fail(&mut self, error: Error) {
+        self.queue.push_back(Err(error));
+    }
+
+    /// Wait for the oldest submitted leaf.
+    pub fn pop(&mut self) -> Option<Fallible<Leaf>> {
+        let (offset, start_idx, rx) = match self.queue.pop_front()? {
+            Ok(pending) => pending,
+            Err(e) => return Some(Err(e)),
+        };
+        Some(rx.recv().map_err(|_| Error::cancelled()).and_then(|bytes| Ok((offset, start_idx, bytes?))))
+    }
+}
+
+/// Decompress the leaf at the given offset unless the iterator has been dropped in the meantime.
+fn decompress_leaf(store: &Weak<Store>, offset: u64, reply: mpsc::SyncSender<Fallible<Arc<[u8]>>>) {
+    let Some(store) = store.upgrade() else {
+        return;
+    };
+    let guard = store.reading();
+    // scanned leaves are not prioritised in the cache
+    let bytes = store.file.stream_at(offset).and_then(|block| decompress(&store, block, false));
+    drop(guard);
+    reply.send(bytes).ok();
+}
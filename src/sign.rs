@@ -0,0 +1,107 @@
+//! Signed leaf blocks, see [`EventFileConfig::signing_key`](crate::EventFileConfig::signing_key).
+//!
+//! A signed leaf carries the hash of the preceding leaf followed by an Ed25519 signature over its own hash,
+//! which covers the leaf header, timestamp table, the predecessor’s hash, and the compressed data. Removing,
+//! inserting, or rewriting a leaf thus breaks the chain of hashes.
+
+use crate::{
+    error::Fallible,
+    formats::{BlockHeader, HasMagic, LeafHeader, LEAF_SIGNED},
+    iter::{decompress, event_bounds, is_blob, leaf_parts, Layout, LeafParts},
+    store::Store,
+    Error,
+};
+use ed25519_dalek::{Keypair, PublicKey, Signature, Signer, Verifier, SIGNATURE_LENGTH};
+use sha2::{Digest, Sha256};
+
+/// length of the hash chaining a leaf to its predecessor
+pub(crate) const HASH_LEN: usize = 32;
+/// length of the trailer of a signed leaf: predecessor hash and signature
+pub(crate) const SIGNATURE_LEN: usize = HASH_LEN + SIGNATURE_LENGTH;
+
+/// SHA-256 hash of a leaf; the first leaf of a file is chained to all zeros.
+pub(crate) type LeafHash = [u8; HASH_LEN];
+
+/// Compute the hash of a leaf from its parts.
+pub(crate) fn leaf_hash(leaf: &LeafHeader, times: &[u8], prev: &LeafHash, compressed: &[u8]) -> LeafHash {
+    let mut hasher = Sha256::new();
+    hasher.update(leaf.as_slice());
+    hasher.update(times);
+    hasher.update(prev);
+    hasher.update(compressed);
+    hasher.finalize().into()
+}
+
+/// Compute the trailer for a new leaf.
+pub(crate) fn sign(keypair: &Keypair, prev: &LeafHash, hash: &LeafHash) -> [u8; SIGNATURE_LEN] {
+    let mut trailer = [0u8; SIGNATURE_LEN];
+    trailer[..HASH_LEN].copy_from_slice(prev);
+    trailer[HASH_LEN..].copy_from_slice(&keypair.sign(hash).to_bytes());
+    trailer
+}
+
+/// Hash of the given leaf as it is stored, regardless of whether it is signed.
+pub(crate) fn stored_hash(parts: &LeafParts<'_>) -> LeafHash {
+    let prev = predecessor(parts);
+    leaf_hash(parts.leaf, parts.times, &prev, parts.compressed)
+}
+
+fn predecessor(parts: &LeafParts<'_>) -> LeafHash {
+    let mut prev = LeafHash::default();
+    if parts.signature.len() == SIGNATURE_LEN {
+        prev.copy_from_slice(&parts.signature[..HASH_LEN]);
+    }
+    prev
+}
+
+/// Check checksums, signatures, and the chain of hashes of all retained blocks, as well as the blobs referenced
+/// by the signed leaves.
+///
+/// The first retained leaf is trusted to name the right predecessor if the leaves before it have been dropped.
+pub(crate) fn verify(store: &Store, key: &PublicKey) -> Fallible<()> {
+    let file = &store.file;
+    let end = file.end_offset();
+    let mut offset = file.start_offset();
+    let mut prev = None;
+    while offset < end {
+        file.verify_block(offset)?;
+        let block: &BlockHeader = file.stream_at(offset)?;
+        if block.level() == 0 {
+            let parts = leaf_parts(file, block)?;
+            if parts.leaf.flags() & LEAF_SIGNED == 0 {
+                return Err(Error::invalid_signature("leaf is not signed", offset));
+            }
+            let stored = predecessor(&parts);
+            let expected = match prev {
+                Some(prev) => prev,
+                None if parts.leaf.start_idx() == 0 => LeafHash::default(),
+                None => stored,
+            };
+            if stored != expected {
+                return Err(Error::invalid_signature("leaf is not chained to its predecessor", offset));
+            }
+            let hash = leaf_hash(parts.leaf, parts.times, &stored, parts.compressed);
+            let signature = Signature::try_from(parts.signature.get(HASH_LEN..).unwrap_or_default())
+                .map_err(|_| Error::invalid_signature("malformed signature", offset))?;
+            key.verify(&hash, &signature)
+                .map_err(|_| Error::invalid_signature("signature does not match", offset))?;
+            prev = Some(hash);
+            verify_blobs(store, block, parts.leaf)?;
+        }
+        offset += BlockHeader::SIZE + ((u64::from(block.length()) + 7) & !7);
+    }
+    Ok(())
+}
+
+/// Check that the blobs referenced by the given leaf match the event hashes it commits to.
+fn verify_blobs(store: &Store, block: &BlockHeader, leaf: &LeafHeader) -> Fallible<()> {
+    let bytes = decompress(store, block, false)?;
+    let layout = Layout::of_leaf(leaf, &bytes);
+    for pos in 0..leaf.count() {
+        if is_blob(&bytes, layout, pos) {
+            let (from, to) = event_bounds(&bytes, layout, pos);
+            store.load_blob(&bytes[from..to], leaf.start_idx() + u64::from(pos))?;
+        }
+    }
+    Ok(())
+}
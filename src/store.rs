@@ -0,0 +1,467 @@
+use crate::{
+    crypt::{self, decrypt, KeyProvider},
+    dict::LoadedDict,
+    error::{ErrCtx, Fallible},
+    formats::{
+        BlockHeader, BranchHeader, EventCheck, EventTime, HasMagic, IndexEntry, JumpEntry, LeafHeader, StagingHeader,
+        StagingHeaderLifted, JUMP_BLOB,
+    },
+    iter::{decompress, event_bounds, find_leaf, index_bounds, is_blob, leaf_parts, Layout, LeafParts, SearchIter},
+    mmap::{MmapFile, Staging},
+    u32_to_usize, usize_to_u64, Cache, Codec, Error, EventIter, EventRef, RangeIter, RevEventIter, RevRangeIter,
+};
+use smallvec::SmallVec;
+use std::{
+    ops::{Bound, Deref, Range, RangeBounds, RangeInclusive},
+    sync::{Arc, Mutex, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard},
+};
+
+/// Lock held exclusively by the writer while it modifies the file and shared by readers on other threads while
+/// they look at the writer’s mapping.
+#[derive(Default)]
+pub(crate) struct FileLock(RwLock<()>);
+
+impl FileLock {
+    pub fn write(&self) -> RwLockWriteGuard<'_, ()> {
+        self.0.write().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn read(&self) -> RwLockReadGuard<'_, ()> {
+        self.0.read().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// The read side of an event file, shared by [`EventFile`](crate::EventFile) and
+/// [`EventFileReader`](crate::EventFileReader).
+pub(crate) struct Store {
+    pub file: MmapFile,
+    pub id: u32,
+    pub cache: Arc<Mutex<Box<dyn Cache>>>,
+    pub keys: Option<Arc<dyn KeyProvider>>,
+    pub codec: Arc<dyn Codec>,
+    /// the most recently used dictionary
+    pub dict: Arc<Mutex<Option<LoadedDict>>>,
+    pub lock: Arc<FileLock>,
+    /// whether this store shares another one’s mapping and therefore needs to take the lock for reading
+    shared: bool,
+}
+
+impl Store {
+    pub fn new(
+        file: MmapFile, id: u32, cache: Box<dyn Cache>, keys: Option<Arc<dyn KeyProvider>>, codec: Arc<dyn Codec>,
+    ) -> Self {
+        Self {
+            file,
+            id,
+            cache: Arc::new(Mutex::new(cache)),
+            keys,
+            codec,
+            dict: Arc::default(),
+            lock: Arc::default(),
+            shared: false,
+        }
+    }
+
+    /// A read-only store sharing this one’s mapping, cache, keys, codec, dictionary, and lock.
+    pub fn share(&self) -> Self {
+        Self {
+            file: self.file.share(),
+            id: self.id,
+            cache: self.cache.clone(),
+            keys: self.keys.clone(),
+            codec: self.codec.clone(),
+            dict: self.dict.clone(),
+            lock: self.lock.clone(),
+            shared: true,
+        }
+    }
+
+    /// Take the lock for looking at the mapping, unless this is the writer’s own store, which cannot be written to
+    /// while it is being read.
+    ///
+    /// Must not be nested, as a waiting writer blocks further readers.
+    pub fn reading(&self) -> Option<RwLockReadGuard<'_, ()>> {
+        self.shared.then(|| self.lock.read())
+    }
+
+    /// The lock taken by [`reading`](Self::reading), for holders that need to borrow the store mutably meanwhile.
+    pub fn read_lock(&self) -> Option<Arc<FileLock>> {
+        self.shared.then(|| self.lock.clone())
+    }
+
+    /// This store, or a copy of it that has been refreshed if the writer has changed the file since this store’s
+    /// view was last refreshed. To be called while holding the lock.
+    pub fn current(&self) -> Fallible<StoreRef<'_>> {
+        if !self.file.is_stale()? {
+            return Ok(StoreRef::Borrowed(self));
+        }
+        let mut store = Self { shared: self.shared, ..self.share() };
+        store.file.refresh()?;
+        Ok(StoreRef::Owned(Box::new(store)))
+    }
+
+    /// Id of the key for encrypting new data, 0 if encryption is not configured.
+    pub fn current_key_id(&self) -> u32 {
+        self.keys.as_ref().map_or(0, |keys| keys.current_key_id())
+    }
+
+    pub fn key(&self, key_id: u32) -> Fallible<[u8; 32]> {
+        self.keys.as_ref().and_then(|keys| keys.key(key_id)).ok_or(Error::unknown_key(key_id))
+    }
+
+    /// Decrypt the staged events at the given positions into the layout of a decompressed leaf, i.e. a jump table
+    /// for all staged events followed by the event data, or the events back to back if they have a fixed size
+    /// (other events appear empty or zeroed, blobs are not resolved).
+    pub fn decrypt_staging(
+        &self, area: Staging, header: &StagingHeaderLifted, positions: Range<u32>,
+    ) -> Fallible<(Vec<u8>, Layout)> {
+        let key = self.key(header.key_id)?;
+        let staging = StagingLayout::of(&self.file, header);
+        let start = staging.event_start();
+        let plain_size = self.file.fixed_layout().map(|(size, _)| u32_to_usize(size));
+        let mut jump_table = Vec::with_capacity(u32_to_usize(header.count + 1) * JumpEntry::LEN);
+        let mut data = Vec::new();
+        // blob flag of the preceding event
+        let mut flags = 0;
+        for pos in 0..=header.count {
+            if plain_size.is_none() {
+                let end = u32::try_from(data.len()).ctx("leaf > 4GiB")?;
+                jump_table.extend_from_slice(JumpEntry::new(end | flags).as_slice());
+            }
+            flags = 0;
+            if pos < header.count && positions.contains(&pos) {
+                let (from, to, blob) = staging.event_range(&self.file, area, u32_to_usize(pos))?;
+                flags = if blob { JUMP_BLOB } else { 0 };
+                let sealed = self.file.area_bytes(area, start + from, start + to)?;
+                let aad = (header.start_idx + u64::from(pos)).to_be_bytes();
+                data.extend_from_slice(&decrypt(&key, &aad, sealed)?);
+            } else if let Some(size) = plain_size.filter(|_| pos < header.count) {
+                data.resize(data.len() + size, 0);
+            }
+        }
+        if let Some(size) = plain_size {
+            return Ok((data, Layout::Fixed(size)));
+        }
+        let layout = Layout::Jump(jump_table.len());
+        jump_table.extend_from_slice(&data);
+        Ok((jump_table, layout))
+    }
+
+    pub fn staging_header(&self) -> Fallible<StagingHeaderLifted> {
+        self.file.staging_at::<StagingHeader>(0).map(|x| x.lift())
+    }
+
+    /// Header of the staging area that is being compressed in the background, if any.
+    pub fn sealed_header(&self) -> Fallible<Option<StagingHeaderLifted>> {
+        match self.file.sealed_offset() {
+            Some(_) => Ok(Some(self.file.area_at::<StagingHeader>(Staging::Sealed, 0)?.lift())),
+            None => Ok(None),
+        }
+    }
+
+    /// Index of the first event that is not yet compressed.
+    pub fn staged_start_idx(&self) -> Fallible<u64> {
+        match self.sealed_header()? {
+            Some(sealed) => Ok(sealed.start_idx),
+            None => Ok(self.staging_header()?.start_idx),
+        }
+    }
+
+    pub fn next_index(&self) -> Fallible<u64> {
+        let header = self.staging_header()?;
+        Ok(header.start_idx + u64::from(header.count))
+    }
+
+    /// Index of the oldest event that has not been dropped by truncation.
+    pub fn first_index(&self) -> Fallible<u64> {
+        let start = self.file.start_offset();
+        let end = self.file.end_offset();
+        // truncation always leaves a leaf at the start, possibly preceded by dictionaries and blobs
+        let mut offset = start;
+        while offset < end {
+            let block: &BlockHeader = self.file.stream_at(offset)?;
+            if block.level() == 0 {
+                return Ok(self.file.stream_after::<_, LeafHeader>(block)?.start_idx());
+            }
+            offset += BlockHeader::SIZE + ((u64::from(block.length()) + 7) & !7);
+        }
+        self.staged_start_idx()
+    }
+
+    /// Translate the range into inclusive bounds, starting unbounded ranges at the first retained event.
+    fn bounds(&self, range: impl RangeBounds<u64>) -> Fallible<RangeInclusive<u64>> {
+        let unbounded = range.start_bound() == Bound::Unbounded;
+        let (mut start, end) = index_bounds(range);
+        if start <= end {
+            let first = self.first_index()?;
+            if unbounded {
+                start = start.max(first);
+            } else if start < first {
+                return Err(Error::data_not_present("event index before first retained event", start, first));
+            }
+        }
+        Ok(start..=end)
+    }
+
+    pub fn get(&self, idx: u64) -> Fallible<Option<EventRef>> {
+        let header = self.staging_header()?;
+        if idx < header.start_idx {
+            let first = self.first_index()?;
+            if idx < first {
+                return Err(Error::data_not_present("event index before first retained event", idx, first));
+            }
+        }
+        if idx >= header.start_idx {
+            if idx >= header.start_idx + u64::from(header.count) {
+                return Ok(None);
+            }
+            return self.staged_event(Staging::Active, &header, idx).map(Some);
+        }
+        if let Some(sealed) = self.sealed_header()? {
+            if idx >= sealed.start_idx {
+                return self.staged_event(Staging::Sealed, &sealed, idx).map(Some);
+            }
+        }
+
+        let offset = match find_leaf(&self.file, header.last_block, idx)? {
+            Some(offset) => offset,
+            None => return Ok(None),
+        };
+        let block: &BlockHeader = self.file.stream_at(offset)?;
+        let leaf: &LeafHeader = self.file.stream_after(block)?;
+        let pos = idx - leaf.start_idx();
+        if pos >= u64::from(leaf.count()) {
+            return Ok(None);
+        }
+        let bytes = decompress(self, block, false)?;
+        let layout = Layout::of_leaf(leaf, &bytes);
+        let (from, to) = event_bounds(&bytes, layout, pos as u32);
+        if is_blob(&bytes, layout, pos as u32) {
+            let blob = self.blob(&bytes[from..to], idx)?;
+            let len = blob.len();
+            return Ok(Some(EventRef::new(blob, 0, len)));
+        }
+        Ok(Some(EventRef::new(bytes, from, to)))
+    }
+
+    /// Read the event with the given index from a staging area that holds it.
+    fn staged_event(&self, area: Staging, header: &StagingHeaderLifted, idx: u64) -> Fallible<EventRef> {
+        let pos = (idx - header.start_idx) as usize;
+        let layout = StagingLayout::of(&self.file, header);
+        let (from, to, blob) = layout.event_range(&self.file, area, pos)?;
+        let start = layout.event_start();
+        let bytes = self.file.area_bytes(area, start + from, start + to)?;
+        let bytes: Arc<[u8]> = match header.key_id {
+            0 => bytes.into(),
+            key_id => decrypt(&self.key(key_id)?, &idx.to_be_bytes(), bytes)?.into(),
+        };
+        let bytes = if blob { self.blob(&bytes, idx)? } else { bytes };
+        let len = bytes.len();
+        Ok(EventRef::new(bytes, 0, len))
+    }
+
+    pub fn iter(&self, range: impl RangeBounds<u64>) -> Fallible<RangeIter<'_>> {
+        StoreRef::Borrowed(self).iter(range)
+    }
+
+    pub fn iter_rev(&self, range: impl RangeBounds<u64>) -> Fallible<RevRangeIter<'_>> {
+        StoreRef::Borrowed(self).iter_rev(range)
+    }
+
+    pub fn events(&self, range: impl RangeBounds<u64>) -> Fallible<EventIter<'_>> {
+        StoreRef::Borrowed(self).events(range)
+    }
+
+    pub fn events_rev(&self, range: impl RangeBounds<u64>) -> Fallible<RevEventIter<'_>> {
+        StoreRef::Borrowed(self).events_rev(range)
+    }
+
+    /// Index of the first retained event with a timestamp at or after `time`, or the next index if there is none.
+    pub fn seek_time(&self, time: u64) -> Fallible<u64> {
+        let header = self.staging_header()?;
+        let top = SearchIter::new(&self.file, header.last_block).collect::<Fallible<SmallVec<[_; 16]>>>()?;
+        for (offset, _) in top.into_iter().rev() {
+            if let Some(idx) = self.seek_block(offset, time)? {
+                return Ok(idx);
+            }
+        }
+        let sealed = self.sealed_header()?.map(|sealed| (Staging::Sealed, sealed));
+        for (area, header) in sealed.into_iter().chain([(Staging::Active, header)]) {
+            let layout = StagingLayout::of(&self.file, &header);
+            for pos in 0..u32_to_usize(header.count) {
+                if self.file.area_at::<EventTime>(area, layout.time_idx(pos))?.time() >= time {
+                    return Ok(header.start_idx + usize_to_u64(pos));
+                }
+            }
+        }
+        self.next_index()
+    }
+
+    /// Find the first event at or after `time` within the given block by descending into the first child whose
+    /// maximum timestamp is large enough.
+    fn seek_block(&self, offset: u64, time: u64) -> Fallible<Option<u64>> {
+        let block: &BlockHeader = self.file.stream_at(offset)?;
+        if block.level() == 0 {
+            let LeafParts { leaf, times, .. } = leaf_parts(&self.file, block)?;
+            if leaf.max_time() < time {
+                return Ok(None);
+            }
+            if times.is_empty() {
+                // all timestamps are zero
+                return Ok(Some(leaf.start_idx()));
+            }
+            let mut times = times.chunks(EventTime::LEN).map(|t| EventTime::from_slice(t).time());
+            return Ok(times.position(|t| t >= time).map(|pos| leaf.start_idx() + usize_to_u64(pos)));
+        }
+        let branch: &BranchHeader = self.file.stream_after(block)?;
+        if branch.max_time() < time {
+            return Ok(None);
+        }
+        let count = (u64::from(block.length()) - BranchHeader::SIZE) / IndexEntry::SIZE;
+        let entries = offset + BlockHeader::SIZE + BranchHeader::SIZE;
+        for i in 0..count {
+            let entry: &IndexEntry = self.file.stream_at(entries + i * IndexEntry::SIZE)?;
+            // children before the start offset have been dropped
+            if entry.max_time() >= time && entry.offset() >= self.file.start_offset() {
+                if let Some(idx) = self.seek_block(entry.offset(), time)? {
+                    return Ok(Some(idx));
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    pub fn iter_time(&self, range: impl RangeBounds<u64>) -> Fallible<EventIter<'_>> {
+        StoreRef::Borrowed(self).iter_time(range)
+    }
+}
+
+/// A store borrowed from an event file or reader, or a refreshed copy of a reader’s store, see
+/// [`Store::current`]; iterators keep it for reading their leaves.
+pub(crate) enum StoreRef<'a> {
+    Borrowed(&'a Store),
+    Owned(Box<Store>),
+}
+
+impl Deref for StoreRef<'_> {
+    type Target = Store;
+
+    fn deref(&self) -> &Self::Target {
+        match self {
+            StoreRef::Borrowed(store) => store,
+            StoreRef::Owned(store) => store,
+        }
+    }
+}
+
+impl<'a> StoreRef<'a> {
+    pub fn iter(self, range: impl RangeBounds<u64>) -> Fallible<RangeIter<'a>> {
+        let (last_block, range) = (self.staging_header()?.last_block, self.bounds(range)?);
+        RangeIter::new(self, last_block, range)
+    }
+
+    pub fn iter_rev(self, range: impl RangeBounds<u64>) -> Fallible<RevRangeIter<'a>> {
+        let (last_block, range) = (self.staging_header()?.last_block, self.bounds(range)?);
+        RevRangeIter::new(self, last_block, range)
+    }
+
+    pub fn events(self, range: impl RangeBounds<u64>) -> Fallible<EventIter<'a>> {
+        Ok(EventIter::new(self.iter(range)?))
+    }
+
+    pub fn events_rev(self, range: impl RangeBounds<u64>) -> Fallible<RevEventIter<'a>> {
+        Ok(RevEventIter::new(self.iter_rev(range)?))
+    }
+
+    /// Events between the positions found by [`seek_time`](Store::seek_time) for the range’s bounds.
+    pub fn iter_time(self, range: impl RangeBounds<u64>) -> Fallible<EventIter<'a>> {
+        let start = match range.start_bound() {
+            Bound::Included(t) => self.seek_time(*t)?,
+            Bound::Excluded(t) => self.seek_time(t.saturating_add(1))?,
+            Bound::Unbounded => self.first_index()?,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(t) => self.seek_time(t.saturating_add(1))?,
+            Bound::Excluded(t) => self.seek_time(*t)?,
+            Bound::Unbounded => self.next_index()?,
+        };
+        self.events(start..end.max(start))
+    }
+}
+
+/// Arrangement of a staging area with room for `capacity` events: the staging header, a jump table (omitted if
+/// all events have the same size), check entries, timestamps, and finally the event data.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct StagingLayout {
+    pub capacity: u32,
+    /// size of every staged event (including the encryption overhead) if the file has a fixed event size
+    pub fixed: Option<usize>,
+}
+
+impl StagingLayout {
+    /// Layout of a staging area with the given header within the given file.
+    pub fn of(file: &MmapFile, header: &StagingHeaderLifted) -> Self {
+        Self::new(file, header.capacity, header.key_id)
+    }
+
+    /// Layout of a staging area with the given capacity whose events are encrypted with the given key.
+    pub fn new(file: &MmapFile, capacity: u32, key_id: u32) -> Self {
+        let overhead = if key_id == 0 { 0 } else { crypt::OVERHEAD };
+        let fixed = file.fixed_layout().map(|(size, _)| u32_to_usize(size) + overhead);
+        Self { capacity, fixed }
+    }
+
+    /// Position of the jump table entry for staged event `idx`, only meaningful without a fixed event size.
+    pub fn jump_idx(self, idx: usize) -> usize {
+        StagingHeader::LEN + idx * JumpEntry::LEN
+    }
+
+    /// End of the header and the jump entries needed to locate the first `count` staged events.
+    pub fn jump_end(self, count: usize) -> usize {
+        match self.fixed {
+            Some(_) => StagingHeader::LEN,
+            None => self.jump_idx(count + 1),
+        }
+    }
+
+    /// Position of the check entry for staged event `idx`.
+    pub fn check_idx(self, idx: usize) -> usize {
+        let table = match self.fixed {
+            Some(_) => StagingHeader::LEN,
+            None => self.jump_idx(u32_to_usize(self.capacity)),
+        };
+        table + idx * EventCheck::LEN
+    }
+
+    /// Position of the timestamp of staged event `idx`.
+    pub fn time_idx(self, idx: usize) -> usize {
+        let table = (self.check_idx(u32_to_usize(self.capacity)) + 7) & !7;
+        table + idx * EventTime::LEN
+    }
+
+    /// Position of the event data.
+    pub fn event_start(self) -> usize {
+        self.time_idx(u32_to_usize(self.capacity))
+    }
+
+    /// Byte range of staged event `pos` relative to the [event data](Self::event_start), and whether the event
+    /// only holds a reference to a blob.
+    pub fn event_range(self, file: &MmapFile, area: Staging, pos: usize) -> Fallible<(usize, usize, bool)> {
+        match self.fixed {
+            Some(size) => Ok((pos * size, (pos + 1) * size, false)),
+            None => {
+                let from = file.area_at::<JumpEntry>(area, self.jump_idx(pos))?.offset();
+                let to = file.area_at::<JumpEntry>(area, self.jump_idx(pos + 1))?;
+                Ok((u32_to_usize(from), u32_to_usize(to.offset()), to.is_blob()))
+            }
+        }
+    }
+
+    /// Length of the event data of the first `count` staged events.
+    pub fn data_len(self, file: &MmapFile, area: Staging, count: usize) -> Fallible<usize> {
+        match self.fixed {
+            Some(size) => Ok(count * size),
+            None => Ok(u32_to_usize(file.area_at::<JumpEntry>(area, self.jump_idx(count))?.offset())),
+        }
+    }
+}
@@ -0,0 +1,186 @@
+use crate::{error::Fallible, Event, EventFileReader, LeafSlice};
+use std::{
+    sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Wakes up in-process subscriptions when the writer has appended events.
+#[derive(Default)]
+pub(crate) struct Notifier {
+    state: Mutex<NotifierState>,
+    cond: Condvar,
+}
+
+#[derive(Default)]
+struct NotifierState {
+    next_index: u64,
+    closed: bool,
+}
+
+impl Notifier {
+    fn state(&self) -> MutexGuard<'_, NotifierState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn notify(&self, next_index: u64) {
+        self.state().next_index = next_index;
+        self.cond.notify_all();
+    }
+
+    pub fn close(&self) {
+        self.state().closed = true;
+        self.cond.notify_all();
+    }
+
+    fn is_closed(&self) -> bool {
+        self.state().closed
+    }
+
+    /// Wait until an event with index `idx` has been appended, returning false upon timeout or when the writer
+    /// is gone.
+    fn wait(&self, idx: u64, deadline: Option<Instant>) -> bool {
+        let mut state = self.state();
+        while state.next_index <= idx && !state.closed {
+            state = match deadline {
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return false;
+                    }
+                    self.cond.wait_timeout(state, deadline - now).unwrap_or_else(PoisonError::into_inner).0
+                }
+                None => self.cond.wait(state).unwrap_or_else(PoisonError::into_inner),
+            };
+        }
+        state.next_index > idx
+    }
+}
+
+enum Wake {
+    /// the writer lives in this process
+    Notify(Arc<Notifier>),
+    /// the writer lives elsewhere, so check the file at this interval
+    Poll(Duration),
+}
+
+/// Live feed of events, see [`EventFile::subscribe`](crate::EventFile::subscribe) and
+/// [`EventFileReader::subscribe`].
+///
+/// Yields the stored events starting at the requested index and then waits for new ones to be appended.
+/// Iteration ends once the writer of an in-process subscription has been dropped and all events have been
+/// yielded; a polling subscription never ends by itself, use [`next_timeout`](Self::next_timeout) instead.
+pub struct Subscription {
+    reader: EventFileReader,
+    wake: Wake,
+    /// index of the next event to be yielded
+    next: u64,
+    current: Option<LeafSlice>,
+}
+
+impl Subscription {
+    pub(crate) fn notified(reader: EventFileReader, notifier: Arc<Notifier>, from_idx: u64) -> Self {
+        Self {
+            reader,
+            wake: Wake::Notify(notifier),
+            next: from_idx,
+            current: None,
+        }
+    }
+
+    pub(crate) fn polling(reader: EventFileReader, interval: Duration, from_idx: u64) -> Self {
+        Self {
+            reader,
+            wake: Wake::Poll(interval),
+            next: from_idx,
+            current: None,
+        }
+    }
+
+    /// Index of the next event to be yielded.
+    pub fn next_index(&self) -> u64 {
+        self.next
+    }
+
+    /// Whether the writer of an in-process subscription has been dropped.
+    pub fn is_closed(&self) -> bool {
+        match &self.wake {
+            Wake::Notify(notifier) => notifier.is_closed(),
+            Wake::Poll(_) => false,
+        }
+    }
+
+    /// Wait at most `timeout` for the next event, returning `None` if none arrived.
+    pub fn next_timeout(&mut self, timeout: Duration) -> Fallible<Option<Event>> {
+        self.next_until(Some(Instant::now() + timeout))
+    }
+
+    fn next_until(&mut self, deadline: Option<Instant>) -> Fallible<Option<Event>> {
+        let mut retried = false;
+        loop {
+            match self.poll() {
+                Ok(Some(event)) => return Ok(Some(event)),
+                Ok(None) => retried = false,
+                // another process may have replaced the staging area while we were reading it
+                Err(_) if matches!(self.wake, Wake::Poll(_)) && !retried => retried = true,
+                Err(e) => return Err(e),
+            }
+            if !self.wait(deadline) {
+                return Ok(None);
+            }
+        }
+    }
+
+    /// Return the next event if it is already stored.
+    fn poll(&mut self) -> Fallible<Option<Event>> {
+        loop {
+            if let Some(event) = self.current.as_ref().and_then(|leaf| leaf.event_at(self.next)) {
+                self.next += 1;
+                return Ok(Some(event));
+            }
+            self.current = None;
+
+            self.reader.refresh()?;
+            let end = self.reader.next_index()?;
+            if self.next >= end {
+                return Ok(None);
+            }
+            // staged events are read one at a time, iterating would copy the whole staging area for each of them
+            if self.next >= self.reader.staged_start_idx()? {
+                let event = self.reader.get(self.next)?.map(|bytes| Event::new(self.next, bytes));
+                self.next += u64::from(event.is_some());
+                return Ok(event);
+            }
+            match self.reader.iter(self.next..end)?.next() {
+                Some(leaf) => self.current = Some(leaf?),
+                None => return Ok(None),
+            }
+        }
+    }
+
+    fn wait(&self, deadline: Option<Instant>) -> bool {
+        match &self.wake {
+            Wake::Notify(notifier) => notifier.wait(self.next, deadline),
+            Wake::Poll(interval) => {
+                let mut sleep = *interval;
+                if let Some(deadline) = deadline {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return false;
+                    }
+                    sleep = sleep.min(deadline - now);
+                }
+                thread::sleep(sleep);
+                true
+            }
+        }
+    }
+}
+
+impl Iterator for Subscription {
+    type Item = Fallible<Event>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_until(None).transpose()
+    }
+}
@@ -0,0 +1,58 @@
+use eventfile::{Error, EventFile, EventFileConfig};
+use tempfile::tempdir;
+
+fn all(f: &EventFile) -> Vec<Vec<u8>> {
+    f.events(..).unwrap().map(|e| e.unwrap().to_vec()).collect()
+}
+
+#[test]
+fn batch() {
+    let dir = tempdir().unwrap();
+    let mut f = EventFile::new(1, dir.path().join("file"), EventFileConfig::new(0).block_event_limit(10)).unwrap();
+
+    assert_eq!(f.append(b"a").unwrap(), 0);
+    assert_eq!(f.append_batch([&b"b"[..], b"c", b"d"]).unwrap(), 1..4);
+    // does not fit into the remaining staging area, so the previous events are compressed first
+    let batch = (0..8u8).map(|i| vec![i]).collect::<Vec<_>>();
+    assert_eq!(f.append_batch(batch.iter().map(|e| &e[..])).unwrap(), 4..12);
+    assert_eq!(f.append_batch([]).unwrap(), 12..12);
+
+    let too_large = vec![&b"x"[..]; 10];
+    let err = f.append_batch(too_large).unwrap_err();
+    assert!(matches!(err, Error::BatchTooLarge { size: 10, limit: 9 }), "{}", err);
+
+    let mut expected = vec![b"a".to_vec(), b"b".to_vec(), b"c".to_vec(), b"d".to_vec()];
+    expected.extend(batch);
+    assert_eq!(all(&f), expected);
+}
+
+#[test]
+fn indices() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("file");
+    let config = || EventFileConfig::new(0).block_event_limit(10);
+
+    let mut f = EventFile::new(1, path.clone(), config()).unwrap();
+    assert!(f.is_empty().unwrap());
+    for i in 0..25 {
+        assert_eq!(f.next_index().unwrap(), i);
+        assert_eq!(f.append(&[0; 5]).unwrap(), i);
+    }
+    drop(f);
+
+    let f = EventFile::new(1, path, config()).unwrap();
+    assert_eq!(f.next_index().unwrap(), 25);
+    assert_eq!(f.len().unwrap(), 25);
+}
+
+#[test]
+fn conditional() {
+    let dir = tempdir().unwrap();
+    let mut f = EventFile::new(1, dir.path().join("file"), EventFileConfig::new(0)).unwrap();
+
+    assert_eq!(f.append_if(0, b"a").unwrap(), 0);
+    assert_eq!(f.append_if(1, b"b").unwrap(), 1);
+    let err = f.append_if(1, b"c").unwrap_err();
+    assert!(matches!(err, Error::WrongOffset { expected: 1, found: 2 }), "{}", err);
+    assert_eq!(all(&f), vec![b"a".to_vec(), b"b".to_vec()]);
+}
@@ -0,0 +1,179 @@
+use eventfile::{Codec, Error, EventFile, EventFileConfig, NoCompression};
+use std::{
+    env, fs, mem,
+    process::{Command, Stdio},
+    sync::{Arc, Condvar, Mutex, PoisonError},
+    thread,
+    time::Duration,
+};
+use tempfile::tempdir;
+
+fn event(i: u64) -> Vec<u8> {
+    format!("event number {:05}", i).into_bytes()
+}
+
+/// A codec storing the bytes uncompressed, but only once the gate has been opened.
+#[derive(Default)]
+struct Gate {
+    open: Mutex<bool>,
+    cond: Condvar,
+}
+
+impl Gate {
+    fn open(&self) {
+        *self.open.lock().unwrap_or_else(PoisonError::into_inner) = true;
+        self.cond.notify_all();
+    }
+}
+
+impl Codec for Gate {
+    fn id(&self) -> u32 {
+        301
+    }
+
+    fn compress(&self, data: &[u8]) -> Result<Vec<u8>, Error> {
+        let open = self.open.lock().unwrap_or_else(PoisonError::into_inner);
+        drop(self.cond.wait_while(open, |open| !*open).unwrap_or_else(PoisonError::into_inner));
+        NoCompression.compress(data)
+    }
+
+    fn decompress(&self, data: &[u8]) -> Result<Vec<u8>, Error> {
+        NoCompression.decompress(data)
+    }
+}
+
+fn config(gate: Arc<Gate>) -> EventFileConfig {
+    EventFileConfig::new(0).block_event_limit(8).codec(gate).background_compression(true)
+}
+
+#[test]
+fn reads_while_compressing() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("file");
+    let gate = Arc::new(Gate::default());
+
+    // the eighth append seals the first seven events, the rest go to the new staging area
+    let mut file = EventFile::new(1, path.clone(), config(gate.clone())).unwrap();
+    for i in 0..12 {
+        assert_eq!(file.append_with_time(&event(i), 100 + i).unwrap(), i);
+    }
+    let reader = file.reader();
+    assert_eq!(file.first_index().unwrap(), 0);
+    assert_eq!(&*file.get(3).unwrap().unwrap(), &*event(3));
+    assert_eq!(&*reader.get(10).unwrap().unwrap(), &*event(10));
+    let events = file.events(..).unwrap().map(|e| e.unwrap().to_vec()).collect::<Vec<_>>();
+    assert_eq!(events, (0..12).map(event).collect::<Vec<_>>());
+    let events = file.events(5..9).unwrap().map(|e| e.unwrap().idx()).collect::<Vec<_>>();
+    assert_eq!(events, (5..9).collect::<Vec<_>>());
+    let rev = file
+        .iter_rev(..)
+        .unwrap()
+        .flat_map(|s| s.unwrap().iter().rev().map(|e| e.to_vec()).collect::<Vec<_>>());
+    assert_eq!(rev.collect::<Vec<_>>(), (0..12).rev().map(event).collect::<Vec<_>>());
+    assert_eq!(file.seek_time(104).unwrap(), 4);
+    assert_eq!(file.seek_time(109).unwrap(), 9);
+    assert!(matches!(file.inclusion_proof(3), Err(Error::DataNotPresent { .. })));
+
+    gate.open();
+    file.finish_compression().unwrap();
+    let root = file.root_hash().unwrap();
+    let proof = file.inclusion_proof(3).unwrap();
+    assert!(EventFile::verify_inclusion(&root, 3, &event(3), &proof));
+    assert!(matches!(file.inclusion_proof(7), Err(Error::DataNotPresent { .. })));
+
+    for i in 12..100 {
+        file.append_with_time(&event(i), 100 + i).unwrap();
+    }
+    drop(file);
+
+    let file = EventFile::new(2, path, config(gate).background_compression(false)).unwrap();
+    let events = file.events(..).unwrap().map(|e| e.unwrap().to_vec()).collect::<Vec<_>>();
+    assert_eq!(events, (0..100).map(event).collect::<Vec<_>>());
+    assert_eq!(file.seek_time(150).unwrap(), 50);
+}
+
+#[test]
+fn sealed_area_compressed_on_open() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("file");
+
+    // leave the file behind with a sealed staging area, as after a crash
+    let mut file = EventFile::new(1, path.clone(), config(Arc::default())).unwrap();
+    for i in 0..10 {
+        file.append(&event(i)).unwrap();
+    }
+    file.flush().unwrap();
+    mem::forget(file);
+
+    let gate = Arc::new(Gate::default());
+    gate.open();
+    let file = EventFile::new(2, path, config(gate)).unwrap();
+    let events = file.events(..).unwrap().map(|e| e.unwrap().to_vec()).collect::<Vec<_>>();
+    assert_eq!(events, (0..10).map(event).collect::<Vec<_>>());
+    let root = file.root_hash().unwrap();
+    let proof = file.inclusion_proof(6).unwrap();
+    assert!(EventFile::verify_inclusion(&root, 6, &event(6), &proof));
+}
+
+/// Keeps appending to the file named by `EVENTFILE_CRASH_FILE` until killed, see `killed_while_compressing`.
+#[test]
+fn crash_child() {
+    let Some(path) = env::var_os("EVENTFILE_CRASH_FILE") else {
+        return;
+    };
+    let config = EventFileConfig::new(0)
+        .block_event_limit(8)
+        .codec(Arc::new(NoCompression))
+        .background_compression(true);
+    let mut file = EventFile::new(1, path.into(), config).unwrap();
+    for i in file.next_index().unwrap().. {
+        file.append(&event(i)).unwrap();
+    }
+}
+
+#[test]
+fn killed_while_compressing() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("file");
+    let config = || EventFileConfig::new(0).block_event_limit(8).codec(Arc::new(NoCompression));
+
+    // kill the writer at different points until it has been caught after writing the leaf for a sealed staging
+    // area but before clearing it, which leaves a block behind the stream end recorded in the header
+    let mut caught = false;
+    for round in 0..100 {
+        if caught && round >= 10 {
+            break;
+        }
+        let mut child = Command::new(env::current_exe().unwrap())
+            .args(["--exact", "crash_child"])
+            .env("EVENTFILE_CRASH_FILE", &path)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap();
+        thread::sleep(Duration::from_millis(100 + round % 10 * 13));
+        child.kill().unwrap();
+        child.wait().unwrap();
+
+        let mut dump = Vec::new();
+        EventFile::open_read_only(1, path.clone(), config()).unwrap().dump_text(0, &mut dump).unwrap();
+        let dump = String::from_utf8(dump).unwrap();
+        let field = |name: &str| {
+            let header = dump.lines().next().unwrap();
+            header.split(' ').find_map(|f| f.strip_prefix(name)).unwrap().parse::<u64>().unwrap()
+        };
+        let behind_end = &fs::read(&path).unwrap()[4096 + field("end=") as usize..];
+        caught |= field("sealed=") != u64::MAX && behind_end.starts_with(b"BlockSta");
+
+        let file = EventFile::new(1, path.clone(), config()).unwrap();
+        let events = file.events(..).unwrap().map(|e| e.unwrap()).collect::<Vec<_>>();
+        assert_eq!(events.len() as u64, file.next_index().unwrap(), "round {}", round);
+        for (i, e) in events.iter().enumerate() {
+            assert_eq!((e.idx(), &**e), (i as u64, &*event(i as u64)), "round {}", round);
+        }
+    }
+    assert!(
+        caught,
+        "writer never killed between writing a leaf and clearing the sealed area"
+    );
+}
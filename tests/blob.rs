@@ -0,0 +1,159 @@
+use ed25519_dalek::{Keypair, PublicKey, SecretKey};
+use eventfile::{Error, EventFile, EventFileConfig, KeyProvider, NoCompression};
+use std::{fs, sync::Arc};
+use tempfile::tempdir;
+
+/// Every third event is large enough to be stored in a blob.
+fn event(i: u64) -> Vec<u8> {
+    let len = if i.is_multiple_of(3) { 20000 } else { 10 };
+    format!("event number {:05} ", i).into_bytes().into_iter().cycle().take(len).collect()
+}
+
+fn all(file: &EventFile) -> Vec<Vec<u8>> {
+    file.events(..).unwrap().map(|e| e.unwrap().to_vec()).collect()
+}
+
+struct Key;
+
+impl KeyProvider for Key {
+    fn current_key_id(&self) -> u32 {
+        1
+    }
+
+    fn key(&self, key_id: u32) -> Option<[u8; 32]> {
+        (key_id == 1).then_some([1; 32])
+    }
+}
+
+fn keypair() -> Keypair {
+    let secret = SecretKey::from_bytes(&[1; 32]).unwrap();
+    let public = PublicKey::from(&secret);
+    Keypair { secret, public }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> usize {
+    haystack.windows(needle.len()).position(|w| w == needle).unwrap()
+}
+
+/// Encode a number in the byte order of the file format.
+fn encode(n: u32) -> [u8; 4] {
+    if cfg!(feature = "native") {
+        n.to_ne_bytes()
+    } else {
+        n.to_be_bytes()
+    }
+}
+
+#[test]
+fn large_events() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("file");
+    let config = || EventFileConfig::new(0).block_event_limit(8).compression_threshold(1000).blob_threshold(100);
+
+    let mut file = EventFile::new(1, path.clone(), config()).unwrap();
+    for i in 0..20 {
+        assert_eq!(file.append(&event(i)).unwrap(), i);
+    }
+    let batch = (20..26).map(event).collect::<Vec<_>>();
+    assert_eq!(file.append_batch(batch.iter().map(|e| &e[..])).unwrap(), 20..26);
+
+    // compressed and staged events alike
+    for i in 0..26 {
+        assert_eq!(&*file.get(i).unwrap().unwrap(), &*event(i));
+    }
+    assert_eq!(all(&file), (0..26).map(event).collect::<Vec<_>>());
+    let rev = file
+        .iter_rev(..)
+        .unwrap()
+        .flat_map(|s| s.unwrap().iter().rev().map(|e| e.to_vec()).collect::<Vec<_>>());
+    assert_eq!(rev.collect::<Vec<_>>(), (0..26).rev().map(event).collect::<Vec<_>>());
+
+    let root = file.root_hash().unwrap();
+    let proof = file.inclusion_proof(9).unwrap();
+    assert!(EventFile::verify_inclusion(&root, 9, &event(9), &proof));
+
+    let mut dump = Vec::new();
+    file.dump_text(0, &mut dump).unwrap();
+    let dump = String::from_utf8(dump).unwrap();
+    assert!(dump.contains("blob: idx=9 len=20000"));
+
+    drop(file);
+    let file = EventFile::new(1, path, config()).unwrap();
+    assert_eq!(all(&file), (0..26).map(event).collect::<Vec<_>>());
+    let reader = file.reader();
+    assert_eq!(&*reader.get(24).unwrap().unwrap(), &*event(24));
+}
+
+#[test]
+fn encrypted_and_truncated() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("file");
+    let config = || {
+        EventFileConfig::new(0)
+            .block_event_limit(4)
+            .blob_threshold(100)
+            .key_provider(Arc::new(Key))
+            .background_compression(true)
+    };
+
+    let mut file = EventFile::new(1, path.clone(), config()).unwrap();
+    for i in 0..40 {
+        file.append(&event(i)).unwrap();
+    }
+    // leaves hold three events each, the blobs of retained events are kept
+    file.truncate_before(20).unwrap();
+    assert_eq!(file.first_index().unwrap(), 18);
+    assert_eq!(all(&file), (18..40).map(event).collect::<Vec<_>>());
+    file.truncate_before(1000).unwrap();
+    assert_eq!(file.first_index().unwrap(), 39);
+    assert_eq!(all(&file), vec![event(39)]);
+
+    drop(file);
+    let file = EventFile::new(1, path, config()).unwrap();
+    assert_eq!(&*file.get(39).unwrap().unwrap(), &*event(39));
+}
+
+#[test]
+fn tampered() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("file");
+    let config = || {
+        EventFileConfig::new(0)
+            .block_event_limit(4)
+            .blob_threshold(100)
+            .codec(Arc::new(NoCompression))
+            .signing_key(keypair())
+    };
+
+    let mut file = EventFile::new(1, path.clone(), config()).unwrap();
+    for i in 0..8 {
+        file.append(&event(i)).unwrap();
+    }
+    file.verify(&keypair().public).unwrap();
+    let mut dump = Vec::new();
+    file.dump_text(0, &mut dump).unwrap();
+    let dump = String::from_utf8(dump).unwrap();
+    drop(file);
+
+    // rewrite the blob of event 0 including its checksum, as an attacker would
+    let lines = dump.lines().collect::<Vec<_>>();
+    let line = lines.windows(2).find(|l| l[1].starts_with("  blob: idx=0 ")).unwrap()[0];
+    let field = |name: &str| line.split(' ').find_map(|f| f.strip_prefix(name)).unwrap();
+    let length = field("length=").parse::<usize>().unwrap();
+    let checksum = u32::from_str_radix(field("checksum=").trim_start_matches("0x"), 16).unwrap();
+    let mut bytes = fs::read(&path).unwrap();
+    let payload = 4096 + find(&bytes[4096..], b"BlobHead");
+    assert_eq!(crc32fast::hash(&bytes[payload..payload + length]), checksum);
+    let checksum_at = 4096 + find(&bytes[4096..payload], &encode(checksum));
+    bytes[payload + 100] ^= 1;
+    let checksum = crc32fast::hash(&bytes[payload..payload + length]);
+    bytes[checksum_at..checksum_at + 4].copy_from_slice(&encode(checksum));
+    fs::write(&path, bytes).unwrap();
+
+    let file = EventFile::open_read_only(1, path, config()).unwrap();
+    assert_eq!(&*file.get(1).unwrap().unwrap(), &*event(1));
+    let err = file.get(0).unwrap_err();
+    assert!(matches!(err, Error::DataCorruption { .. }), "{}", err);
+    let err = file.verify(&keypair().public).unwrap_err();
+    assert!(matches!(err, Error::DataCorruption { .. }), "{}", err);
+}
@@ -0,0 +1,52 @@
+use eventfile::{Codec, Error, EventFile, EventFileConfig, Lz4, NoCompression, Zstd};
+use std::sync::Arc;
+use tempfile::tempdir;
+
+fn event(i: u64) -> Vec<u8> {
+    format!("event number {:05}", i).into_bytes()
+}
+
+/// A codec unknown to the library, storing the bytes reversed.
+struct Reverse;
+
+impl Codec for Reverse {
+    fn id(&self) -> u32 {
+        300
+    }
+
+    fn compress(&self, data: &[u8]) -> Result<Vec<u8>, Error> {
+        Ok(data.iter().rev().copied().collect())
+    }
+
+    fn decompress(&self, data: &[u8]) -> Result<Vec<u8>, Error> {
+        Ok(data.iter().rev().copied().collect())
+    }
+}
+
+#[test]
+fn mixed_codecs() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("file");
+    let config = |codec: Arc<dyn Codec>| EventFileConfig::new(0).block_event_limit(4).codec(codec);
+
+    let codecs: Vec<Arc<dyn Codec>> =
+        vec![Arc::new(Zstd { level: 3 }), Arc::new(Lz4), Arc::new(NoCompression), Arc::new(Reverse)];
+    let mut next = 0;
+    for codec in codecs {
+        let mut file = EventFile::new(1, path.clone(), config(codec)).unwrap();
+        for i in next..next + 10 {
+            file.append(&event(i)).unwrap();
+        }
+        next += 10;
+        file.flush().unwrap();
+        let events = file.events(..).unwrap().map(|e| e.unwrap().to_vec()).collect::<Vec<_>>();
+        assert_eq!(events, (0..next).map(event).collect::<Vec<_>>());
+    }
+
+    // built-in codecs are always available, custom ones need to be configured
+    let reader = EventFile::open_read_only(2, path.clone(), config(Arc::new(Reverse))).unwrap();
+    assert_eq!(reader.events(..).unwrap().count(), 40);
+    let reader = EventFile::open_read_only(3, path, EventFileConfig::new(0)).unwrap();
+    assert_eq!(&*reader.get(25).unwrap().unwrap(), &*event(25));
+    assert!(matches!(reader.get(30), Err(Error::UnknownCodec(300))));
+}
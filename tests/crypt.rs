@@ -0,0 +1,64 @@
+use eventfile::{Error, EventFile, EventFileConfig, KeyProvider};
+use std::{
+    fs,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    },
+};
+use tempfile::tempdir;
+
+struct Keys {
+    current: AtomicU32,
+}
+
+impl KeyProvider for Keys {
+    fn current_key_id(&self) -> u32 {
+        self.current.load(Ordering::Relaxed)
+    }
+
+    fn key(&self, key_id: u32) -> Option<[u8; 32]> {
+        (1..=2).contains(&key_id).then_some([key_id as u8; 32])
+    }
+}
+
+fn event(i: u64) -> Vec<u8> {
+    format!("secret number {:05}", i).into_bytes()
+}
+
+#[test]
+fn encrypted() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("file");
+    let keys = Arc::new(Keys { current: AtomicU32::new(1) });
+    let config = || EventFileConfig::new(0).block_event_limit(4).key_provider(keys.clone());
+
+    let mut file = EventFile::new(1, path.clone(), config()).unwrap();
+    for i in 0..10 {
+        file.append(&event(i)).unwrap();
+    }
+    // rotate the key, older blocks remain readable
+    keys.current.store(2, Ordering::Relaxed);
+    for i in 10..20 {
+        file.append(&event(i)).unwrap();
+    }
+    file.flush().unwrap();
+
+    let events = file.events(..).unwrap().map(|e| e.unwrap().to_vec()).collect::<Vec<_>>();
+    assert_eq!(events, (0..20).map(event).collect::<Vec<_>>());
+    assert_eq!(&*file.get(19).unwrap().unwrap(), &*event(19));
+    assert_eq!(&*file.get(3).unwrap().unwrap(), &*event(3));
+
+    // neither compressed nor staged events are stored in clear
+    let bytes = fs::read(&path).unwrap();
+    assert!(!bytes.windows(6).any(|w| w == b"secret"));
+
+    let reader = EventFile::open_read_only(2, path.clone(), config()).unwrap();
+    assert_eq!(reader.events(..).unwrap().count(), 20);
+
+    // without the keys, nothing can be read
+    drop(file);
+    let reader = EventFile::open_read_only(3, path, EventFileConfig::new(0)).unwrap();
+    assert!(matches!(reader.get(0), Err(Error::UnknownKey(1))));
+    assert!(matches!(reader.get(19), Err(Error::UnknownKey(2))));
+}
@@ -0,0 +1,88 @@
+use eventfile::{EventFile, EventFileConfig, Retention, Zstd};
+use std::{fs, sync::Arc};
+use tempfile::tempdir;
+
+fn event(i: u64) -> Vec<u8> {
+    let kinds = ["order_placed", "order_shipped", "payment_received"];
+    format!(
+        r#"{{"type":"{}","customer":"customer-{:04}","amount":{},"currency":"EUR","seq":{}}}"#,
+        kinds[(i % 3) as usize],
+        i * 7919 % 1000,
+        i * 31 % 500,
+        i
+    )
+    .into_bytes()
+}
+
+fn config() -> EventFileConfig {
+    EventFileConfig::new(0).block_event_limit(8).codec(Arc::new(Zstd { level: 3 }))
+}
+
+#[test]
+fn trained() {
+    let dir = tempdir().unwrap();
+    let plain_path = dir.path().join("plain");
+    let dict_path = dir.path().join("dict");
+
+    let mut plain = EventFile::new(1, plain_path.clone(), config()).unwrap();
+    let mut file = EventFile::new(2, dict_path.clone(), config().dictionary_training(300, 2048)).unwrap();
+    for i in 0..600 {
+        plain.append(&event(i)).unwrap();
+        file.append(&event(i)).unwrap();
+    }
+    assert_eq!(plain.dictionary_id().unwrap(), 0);
+    assert_eq!(file.dictionary_id().unwrap(), 1);
+    let events = file.events(..).unwrap().map(|e| e.unwrap().to_vec()).collect::<Vec<_>>();
+    assert_eq!(events, (0..600).map(event).collect::<Vec<_>>());
+    drop(plain);
+    drop(file);
+
+    // the dictionary more than pays for itself
+    let plain_len = fs::metadata(&plain_path).unwrap().len();
+    let dict_len = fs::metadata(&dict_path).unwrap().len();
+    assert!(dict_len < plain_len, "{} >= {}", dict_len, plain_len);
+
+    let reader = EventFile::open_read_only(3, dict_path, config()).unwrap();
+    assert_eq!(&*reader.get(599).unwrap().unwrap(), &*event(599));
+    assert_eq!(reader.events(..).unwrap().count(), 600);
+}
+
+#[test]
+fn versions_and_retention() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("file");
+    let config = || config().retention(Retention::Events(100));
+
+    let mut file = EventFile::new(1, path.clone(), config()).unwrap();
+    for i in 0..300 {
+        file.append(&event(i)).unwrap();
+    }
+    file.train_dictionary(..300, 2048).unwrap();
+    for i in 300..600 {
+        file.append(&event(i)).unwrap();
+    }
+    assert_eq!(file.dictionary_id().unwrap(), 1);
+    file.train_dictionary(.., 2048).unwrap();
+    for i in 600..900 {
+        file.append(&event(i)).unwrap();
+    }
+    assert_eq!(file.dictionary_id().unwrap(), 2);
+
+    // the blocks holding both dictionaries have been dropped, but the latest one has been kept
+    let first = file.first_index().unwrap();
+    assert!(first > 600 && first <= 800, "{}", first);
+    let events = file.events(..).unwrap().map(|e| e.unwrap().to_vec()).collect::<Vec<_>>();
+    assert_eq!(events, (first..900).map(event).collect::<Vec<_>>());
+
+    // truncating with staged events works as well
+    file.append(&event(900)).unwrap();
+    file.truncate_before(898).unwrap();
+    drop(file);
+
+    let mut file = EventFile::new(1, path, config()).unwrap();
+    assert_eq!(file.dictionary_id().unwrap(), 2);
+    assert_eq!(&*file.get(900).unwrap().unwrap(), &*event(900));
+    file.append(&event(901)).unwrap();
+    let events = file.events(..).unwrap().map(|e| e.unwrap().to_vec()).collect::<Vec<_>>();
+    assert_eq!(events, (file.first_index().unwrap()..902).map(event).collect::<Vec<_>>());
+}
@@ -0,0 +1,149 @@
+use eventfile::{Error, EventFile, EventFileConfig, KeyProvider, NoCompression};
+use std::{fs, sync::Arc};
+use tempfile::tempdir;
+
+fn event(i: u64) -> Vec<u8> {
+    i.to_be_bytes().repeat(4)
+}
+
+struct Key;
+
+impl KeyProvider for Key {
+    fn current_key_id(&self) -> u32 {
+        1
+    }
+
+    fn key(&self, key_id: u32) -> Option<[u8; 32]> {
+        (key_id == 1).then_some([7; 32])
+    }
+}
+
+fn config() -> EventFileConfig {
+    EventFileConfig::new(0).block_event_limit(4).codec(Arc::new(NoCompression))
+}
+
+#[test]
+fn fixed_event_size() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("fixed");
+    let fixed = || config().fixed_event_size(32);
+
+    let mut file = EventFile::new(1, path.clone(), fixed()).unwrap();
+    for i in 0..1000 {
+        file.append(&event(i)).unwrap();
+    }
+    assert!(matches!(
+        file.append(b"short"),
+        Err(Error::WrongEventSize { expected: 32, found: 5 })
+    ));
+    let batch = [event(1000), b"short".to_vec()];
+    assert!(matches!(
+        file.append_batch(batch.iter().map(|e| &e[..])),
+        Err(Error::WrongEventSize { .. })
+    ));
+    // leaves hold three events each, so only two more fit into the current one
+    let batch = (1000..1003).map(event).collect::<Vec<_>>();
+    assert!(matches!(
+        file.append_batch(batch.iter().map(|e| &e[..])),
+        Err(Error::BatchTooLarge { size: 3, limit: 2 })
+    ));
+    assert_eq!(file.append_batch(batch[..2].iter().map(|e| &e[..])).unwrap(), 1000..1002);
+    for i in 1002..1050 {
+        file.append(&event(i)).unwrap();
+    }
+    drop(file);
+
+    // the layout recorded in the file wins over the configuration
+    let mut file = EventFile::new(1, path.clone(), config().block_event_limit(100)).unwrap();
+    assert!(matches!(file.append(b"short"), Err(Error::WrongEventSize { .. })));
+    for i in 1050..1100 {
+        file.append(&event(i)).unwrap();
+    }
+    let mut dump = Vec::new();
+    file.dump_text(0, &mut dump).unwrap();
+    let dump = String::from_utf8(dump).unwrap();
+    assert!(dump.contains("staging: last_block=") && dump.contains("capacity=4 key=0"));
+    assert!(dump.contains("  fixed size=32"));
+
+    for i in (0..1100).step_by(7) {
+        assert_eq!(&*file.get(i).unwrap().unwrap(), &*event(i));
+    }
+    let events = file.events(500..520).unwrap().map(|e| e.unwrap().to_vec()).collect::<Vec<_>>();
+    assert_eq!(events, (500..520).map(event).collect::<Vec<_>>());
+    let rev = file
+        .iter_rev(990..1010)
+        .unwrap()
+        .flat_map(|s| s.unwrap().iter().rev().map(|e| e.to_vec()).collect::<Vec<_>>());
+    assert_eq!(rev.collect::<Vec<_>>(), (990..1010).rev().map(event).collect::<Vec<_>>());
+
+    let root = file.root_hash().unwrap();
+    let proof = file.inclusion_proof(777).unwrap();
+    assert!(EventFile::verify_inclusion(&root, 777, &event(777), &proof));
+    drop(file);
+
+    let file = EventFile::new(1, path.clone(), fixed()).unwrap();
+    let events = file.events(..).unwrap().map(|e| e.unwrap().to_vec()).collect::<Vec<_>>();
+    assert_eq!(events, (0..1100).map(event).collect::<Vec<_>>());
+
+    // the same events take more space with jump tables
+    let other = dir.path().join("variable");
+    let mut file = EventFile::new(1, other.clone(), config()).unwrap();
+    for i in 0..1100 {
+        file.append(&event(i)).unwrap();
+    }
+    drop(file);
+    assert!(fs::metadata(&path).unwrap().len() < fs::metadata(&other).unwrap().len());
+}
+
+#[test]
+fn encrypted() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("fixed");
+    let config = || config().fixed_event_size(32).key_provider(Arc::new(Key)).background_compression(true);
+
+    let mut file = EventFile::new(1, path.clone(), config()).unwrap();
+    for i in 0..50 {
+        file.append(&event(i)).unwrap();
+    }
+    // compressed, sealed, and staged events alike
+    assert_eq!(&*file.get(10).unwrap().unwrap(), &*event(10));
+    let events = file.events(..).unwrap().map(|e| e.unwrap().to_vec()).collect::<Vec<_>>();
+    assert_eq!(events, (0..50).map(event).collect::<Vec<_>>());
+    drop(file);
+
+    let file = EventFile::new(1, path, config()).unwrap();
+    assert_eq!(file.dropped_events(), 0);
+    assert_eq!(&*file.get(49).unwrap().unwrap(), &*event(49));
+    let events = file.events(40..).unwrap().map(|e| e.unwrap().to_vec()).collect::<Vec<_>>();
+    assert_eq!(events, (40..50).map(event).collect::<Vec<_>>());
+}
+
+#[test]
+fn truncated() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("fixed");
+    let config = || EventFileConfig::new(0).block_event_limit(3).fixed_event_size(8);
+    let event = |i: u64| i.to_be_bytes();
+
+    let mut file = EventFile::new(1, path.clone(), config()).unwrap();
+    for i in 0..40 {
+        file.append(&event(i)).unwrap();
+    }
+    // branches built from here on start in the middle of the file
+    file.truncate_before(36).unwrap();
+    for i in 40..120 {
+        file.append(&event(i)).unwrap();
+    }
+    let first = file.first_index().unwrap();
+    for i in first..120 {
+        assert_eq!(&*file.get(i).unwrap().unwrap(), &event(i), "at {}", i);
+    }
+    drop(file);
+
+    let file = EventFile::new(1, path, config()).unwrap();
+    let events = file.events(..).unwrap().map(|e| e.unwrap().to_vec()).collect::<Vec<_>>();
+    assert_eq!(events, (first..120).map(|i| event(i).to_vec()).collect::<Vec<_>>());
+    let root = file.root_hash().unwrap();
+    let proof = file.inclusion_proof(100).unwrap();
+    assert!(EventFile::verify_inclusion(&root, 100, &event(100), &proof));
+}
@@ -0,0 +1,121 @@
+use eventfile::{Durability, Error, EventFile, EventFileConfig};
+use std::{
+    fs::OpenOptions,
+    io::{Read, Seek, SeekFrom, Write},
+    time::Duration,
+};
+use tempfile::tempdir;
+
+#[test]
+fn corrupted_leaf() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("file");
+    let config = || EventFileConfig::new(0).compression_threshold(100).block_event_limit(20);
+
+    let mut f = EventFile::new(1, path.clone(), config()).unwrap();
+    for i in 0..10u8 {
+        f.append(&[i; 30]).unwrap();
+    }
+    drop(f);
+
+    // flip a bit within the compressed data of the first leaf
+    let mut file = OpenOptions::new().read(true).write(true).open(&path).unwrap();
+    let mut byte = [0u8];
+    file.seek(SeekFrom::Start(4096 + 150)).unwrap();
+    file.read_exact(&mut byte).unwrap();
+    byte[0] ^= 1;
+    file.seek(SeekFrom::Start(4096 + 150)).unwrap();
+    file.write_all(&byte).unwrap();
+    drop(file);
+
+    let f = EventFile::new(1, path, config()).unwrap();
+    let err = f.iter(..).unwrap().next().unwrap().err().unwrap();
+    assert!(matches!(err, Error::ChecksumMismatch { offset: 0, .. }), "{}", err);
+
+    let mut dump = Vec::new();
+    f.dump_text(0, &mut dump).unwrap();
+    let dump = String::from_utf8(dump).unwrap();
+    assert!(dump.contains("checksum mismatch in block at offset 0"), "{}", dump);
+}
+
+#[test]
+fn torn_staging_write() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("file");
+    let config = || EventFileConfig::new(0).block_event_limit(20).durability(Durability::Always);
+
+    let mut f = EventFile::new(1, path.clone(), config()).unwrap();
+    for i in 0..5u8 {
+        f.append(&[i; 10]).unwrap();
+    }
+    drop(f);
+
+    // staging header, 20 jump entries, 20 event checks and 20 timestamps precede the event data
+    let pos = 4096 + 72 + 20 * 4 + 20 * 8 + 20 * 8 + 4 * 10 + 3;
+    let mut file = OpenOptions::new().read(true).write(true).open(&path).unwrap();
+    file.seek(SeekFrom::Start(pos)).unwrap();
+    file.write_all(&[0xff]).unwrap();
+    drop(file);
+
+    let mut f = EventFile::new(1, path.clone(), config()).unwrap();
+    assert_eq!(f.dropped_events(), 1);
+    let events = f.iter(..).unwrap().flat_map(|l| l.unwrap().iter().map(|e| e.to_vec()).collect::<Vec<_>>());
+    assert_eq!(events.collect::<Vec<_>>(), (0..4u8).map(|i| vec![i; 10]).collect::<Vec<_>>());
+
+    f.append(&[42; 3]).unwrap();
+    drop(f);
+    let f = EventFile::new(1, path, config()).unwrap();
+    assert_eq!(f.dropped_events(), 0);
+    assert_eq!(
+        f.iter(4..).unwrap().next().unwrap().unwrap().iter().collect::<Vec<_>>(),
+        vec![&[42; 3]]
+    );
+}
+
+#[test]
+fn torn_batch() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("file");
+    let config = || EventFileConfig::new(0).block_event_limit(20);
+
+    let mut f = EventFile::new(1, path.clone(), config()).unwrap();
+    f.append(&[1; 10]).unwrap();
+    f.append_batch([&[2; 10][..], &[3; 10], &[4; 10]]).unwrap();
+    drop(f);
+
+    // damage the second event of the batch
+    let pos = 4096 + 72 + 20 * 4 + 20 * 8 + 20 * 8 + 2 * 10;
+    let mut file = OpenOptions::new().read(true).write(true).open(&path).unwrap();
+    file.seek(SeekFrom::Start(pos)).unwrap();
+    file.write_all(&[0xff]).unwrap();
+    drop(file);
+
+    let f = EventFile::new(1, path, config()).unwrap();
+    assert_eq!(f.dropped_events(), 3);
+    let events = f.iter(..).unwrap().flat_map(|l| l.unwrap().iter().map(|e| e.to_vec()).collect::<Vec<_>>());
+    assert_eq!(events.collect::<Vec<_>>(), vec![vec![1; 10]]);
+}
+
+#[test]
+fn durability() {
+    let dir = tempdir().unwrap();
+    let synced = |durability, name| {
+        let config = EventFileConfig::new(0).block_event_limit(5).durability(durability);
+        let mut f = EventFile::new(1, dir.path().join(name), config).unwrap();
+        (0..6u8)
+            .map(|i| {
+                f.append(&[i; 10]).unwrap();
+                f.synced_index().unwrap()
+            })
+            .collect::<Vec<_>>()
+    };
+
+    // compressing the fourth event syncs the leaf
+    assert_eq!(synced(Durability::None, "none"), [0, 0, 0, 4, 4, 4]);
+    assert_eq!(synced(Durability::Always, "always"), [1, 2, 3, 4, 5, 6]);
+    let hour = Duration::from_secs(3600);
+    let periodic = synced(Durability::Periodic { events: 2, interval: hour }, "events");
+    assert_eq!(periodic, [0, 2, 2, 4, 4, 6]);
+    let periodic = synced(Durability::Periodic { events: 100, interval: Duration::ZERO }, "interval");
+    assert_eq!(periodic, [1, 2, 3, 4, 5, 6]);
+}
@@ -0,0 +1,71 @@
+use eventfile::{Digest, Error, EventFile, EventFileConfig, ProofStep};
+use tempfile::tempdir;
+
+fn event(i: u64) -> Vec<u8> {
+    i.to_be_bytes().to_vec()
+}
+
+#[test]
+fn inclusion() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("file");
+    let config = || EventFileConfig::new(0).block_event_limit(4);
+    let mut file = EventFile::new(1, path.clone(), config()).unwrap();
+    assert_eq!(file.root_hash().unwrap(), Digest::default());
+    // 20 leaves, the first 16 of which are referenced by a branch
+    for i in 0..61 {
+        file.append(&event(i)).unwrap();
+    }
+
+    let root = file.root_hash().unwrap();
+    assert_ne!(root, Digest::default());
+    for idx in [0, 1, 29, 47, 48, 59] {
+        let proof = file.inclusion_proof(idx).unwrap();
+        assert!(EventFile::verify_inclusion(&root, idx, &event(idx), &proof), "{}", idx);
+        assert!(!EventFile::verify_inclusion(&root, idx, &event(idx + 1), &proof), "{}", idx);
+        assert!(!EventFile::verify_inclusion(&root, idx + 1, &event(idx), &proof), "{}", idx);
+    }
+    let steps = file.inclusion_proof(0).unwrap().steps;
+    assert!(steps.iter().any(|step| matches!(step, ProofStep::Branch { .. })));
+    assert!(matches!(file.inclusion_proof(60), Err(Error::DataNotPresent { .. })));
+    let proof = file.inclusion_proof(10).unwrap();
+
+    // the root changes as the log grows, while old roots remain provable via new proofs
+    for i in 61..70 {
+        file.append(&event(i)).unwrap();
+    }
+    let new_root = file.root_hash().unwrap();
+    assert_ne!(root, new_root);
+    assert!(!EventFile::verify_inclusion(&new_root, 10, &event(10), &proof));
+    let new_proof = file.inclusion_proof(10).unwrap();
+    assert!(EventFile::verify_inclusion(&new_root, 10, &event(10), &new_proof));
+
+    // dropped blocks are not needed for proving the remaining events
+    file.truncate_before(30).unwrap();
+    let proof = file.inclusion_proof(40).unwrap();
+    assert!(EventFile::verify_inclusion(&new_root, 40, &event(40), &proof));
+
+    let reader = EventFile::open_read_only(2, path, config()).unwrap();
+    assert_eq!(reader.root_hash().unwrap(), new_root);
+}
+
+#[test]
+fn proof_size() {
+    let dir = tempdir().unwrap();
+    let mut file = EventFile::new(1, dir.path().join("file"), EventFileConfig::new(0).block_event_limit(2)).unwrap();
+    // one event per leaf: two branches over 16 leaves each, then four more leaves
+    for i in 0..36 {
+        file.append(&event(i)).unwrap();
+    }
+
+    let root = file.root_hash().unwrap();
+    for idx in [0, 15, 16, 31, 32, 35] {
+        let proof = file.inclusion_proof(idx).unwrap();
+        assert!(EventFile::verify_inclusion(&root, idx, &event(idx), &proof), "{}", idx);
+    }
+    // the second branch is reached through its first leaf instead of through all 16 of them
+    let steps = file.inclusion_proof(0).unwrap().steps;
+    assert_eq!(steps.len(), 8);
+    assert!(matches!(steps[2], ProofStep::Next { level: 0, .. }));
+    assert!(matches!(steps[3], ProofStep::Branch { level: 1, pos: 0, count: 16, .. }));
+}
@@ -0,0 +1,109 @@
+use eventfile::{EventFile, EventFileConfig};
+use tempfile::{tempdir, TempDir};
+
+const N: u64 = 120;
+
+fn event(i: u64) -> Vec<u8> {
+    i.to_string().repeat((i % 7 + 1) as usize).into_bytes()
+}
+
+/// small leaves so that branch blocks are built
+fn file() -> (TempDir, EventFile) {
+    let dir = tempdir().unwrap();
+    let config = EventFileConfig::new(0).block_event_limit(4);
+    let mut f = EventFile::new(1, dir.path().join("file"), config).unwrap();
+    for i in 0..N {
+        f.append(&event(i)).unwrap();
+    }
+    (dir, f)
+}
+
+#[test]
+fn get() {
+    let (_dir, f) = file();
+    for i in 0..N {
+        assert_eq!(&*f.get(i).unwrap().unwrap(), &*event(i), "at {}", i);
+    }
+    assert!(f.get(N).unwrap().is_none());
+    assert!(f.get(u64::MAX).unwrap().is_none());
+}
+
+#[test]
+fn reverse() {
+    let (_dir, f) = file();
+    let rev = |r: std::ops::RangeInclusive<u64>| {
+        f.iter_rev(r)
+            .unwrap()
+            .flat_map(|l| l.unwrap().iter().rev().map(|e| e.to_vec()).collect::<Vec<_>>())
+            .collect::<Vec<_>>()
+    };
+    for (start, end) in [(0, N - 1), (0, 0), (N - 1, N - 1), (N - 10, N + 10), (5, 17), (30, 29)] {
+        let expected = (start..=end.min(N - 1)).rev().map(event).collect::<Vec<_>>();
+        assert_eq!(rev(start..=end), expected, "range {}..={}", start, end);
+    }
+
+    let last = f.iter_rev(..).unwrap().next().unwrap().unwrap();
+    let mut events = last.iter();
+    assert_eq!(events.next_back().unwrap(), &*event(N - 1));
+    assert_eq!(events.next_back().unwrap(), &*event(N - 2));
+}
+
+#[test]
+fn events() {
+    let (_dir, f) = file();
+    for (start, end) in [(0, N - 1), (7, 7), (N - 5, N + 5), (5, 17)] {
+        let events = f.events(start..=end).unwrap().map(|e| e.unwrap()).collect::<Vec<_>>();
+        assert_eq!(events.len() as u64, end.min(N - 1) - start + 1);
+        for (e, i) in events.iter().zip(start..) {
+            assert_eq!(e.idx(), i);
+            assert_eq!(&**e, &*event(i));
+        }
+    }
+    assert_eq!(f.events(N..).unwrap().count(), 0);
+}
+
+#[test]
+fn events_rev() {
+    let (_dir, mut f) = file();
+    // leave events in the staging area, behind the compressed leaves
+    f.append(&event(N)).unwrap();
+    for (start, end) in [(0, N), (N - 7, N), (5, 17), (N, N)] {
+        let events = f.events_rev(start..=end).unwrap().map(|e| e.unwrap()).collect::<Vec<_>>();
+        let indices = events.iter().map(|e| e.idx()).collect::<Vec<_>>();
+        assert_eq!(indices, (start..=end).rev().collect::<Vec<_>>(), "range {}..={}", start, end);
+        for e in &events {
+            assert_eq!(&**e, &*event(e.idx()));
+        }
+    }
+    assert_eq!(f.events_rev(N + 1..).unwrap().count(), 0);
+}
+
+#[test]
+fn staged_after_branch() {
+    let dir = tempdir().unwrap();
+    let config = EventFileConfig::new(0).block_event_limit(4);
+    let mut f = EventFile::new(1, dir.path().join("file"), config).unwrap();
+    // 16 leaves of three events each, the last of which is followed by a branch
+    for i in 0..49 {
+        f.append(&event(i)).unwrap();
+    }
+    let events = f.events(..).unwrap().map(|e| e.unwrap().to_vec()).collect::<Vec<_>>();
+    assert_eq!(events, (0..49).map(event).collect::<Vec<_>>());
+}
+
+#[test]
+fn read_ahead() {
+    let (_dir, f) = file();
+    for (start, end) in [(0, N - 1), (7, 7), (N - 5, N + 5), (5, 17), (30, 29)] {
+        let plain = f.events(start..=end).unwrap().map(|e| e.unwrap().to_vec()).collect::<Vec<_>>();
+        for leaves in [0, 1, 3, 64] {
+            let events = f.events(start..=end).unwrap().read_ahead(leaves);
+            let events = events.map(|e| e.unwrap().to_vec()).collect::<Vec<_>>();
+            assert_eq!(events, plain, "range {}..={} reading {} leaves ahead", start, end, leaves);
+        }
+    }
+
+    // dropping the iterator early stops the workers
+    let mut leaves = f.iter(..).unwrap().read_ahead(4);
+    assert_eq!(leaves.next().unwrap().unwrap().start_idx(), 0);
+}
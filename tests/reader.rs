@@ -0,0 +1,134 @@
+use eventfile::{EventFile, EventFileConfig};
+use std::thread;
+use tempfile::tempdir;
+
+#[test]
+fn read_only() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("file");
+    let config = || EventFileConfig::new(7).block_event_limit(10);
+
+    assert!(EventFile::open_read_only(1, path.clone(), config()).is_err());
+
+    let mut writer = EventFile::new(1, path.clone(), config()).unwrap();
+    for i in 0..3u8 {
+        writer.append(&[i]).unwrap();
+    }
+
+    assert!(EventFile::open_read_only(1, path.clone(), EventFileConfig::new(8)).is_err());
+    let mut reader = EventFile::open_read_only(2, path, config()).unwrap();
+    let all = |r: &eventfile::EventFileReader| r.events(..).unwrap().map(|e| e.unwrap()[0]).collect::<Vec<_>>();
+    assert_eq!(all(&reader), vec![0, 1, 2]);
+
+    // staged events are visible right away
+    writer.append(&[3]).unwrap();
+    assert_eq!(reader.next_index().unwrap(), 4);
+    assert_eq!(&*reader.get(3).unwrap().unwrap(), &[3]);
+
+    // compressed blocks are picked up with or without a refresh
+    for i in 4..25u8 {
+        writer.append(&[i]).unwrap();
+    }
+    assert_eq!(all(&reader), (0..25).collect::<Vec<_>>());
+    assert_eq!(&*reader.get(7).unwrap().unwrap(), &[7]);
+    reader.refresh().unwrap();
+    assert_eq!(all(&reader), (0..25).collect::<Vec<_>>());
+}
+
+#[test]
+fn shared() {
+    fn send_sync<T: Send + Sync>() {}
+    send_sync::<EventFile>();
+    send_sync::<eventfile::EventFileReader>();
+
+    let dir = tempdir().unwrap();
+    let config = EventFileConfig::new(0).block_event_limit(10);
+    let mut writer = EventFile::new(1, dir.path().join("file"), config).unwrap();
+    for i in 0..25u8 {
+        writer.append(&[i]).unwrap();
+    }
+
+    let reader = writer.reader();
+    thread::scope(|s| {
+        for _ in 0..4 {
+            s.spawn(|| {
+                let events = reader.events(..).unwrap().map(|e| e.unwrap()[0]).collect::<Vec<_>>();
+                assert_eq!(events, (0..25).collect::<Vec<_>>());
+            });
+        }
+    });
+
+    for i in 25..50u8 {
+        writer.append(&[i]).unwrap();
+    }
+    let mut clone = reader.clone();
+    clone.refresh().unwrap();
+    assert_eq!(clone.len().unwrap(), 50);
+    assert_eq!(&*clone.get(42).unwrap().unwrap(), &[42]);
+}
+
+#[test]
+fn concurrent() {
+    let dir = tempdir().unwrap();
+    let config = EventFileConfig::new(0).block_event_limit(10).compression_threshold(200);
+    let mut writer = EventFile::new(1, dir.path().join("file"), config).unwrap();
+    let event = |i: u64| i.to_be_bytes().repeat(i as usize % 5 + 1);
+
+    // readers look at the file while the writer appends and compresses, without refreshing
+    let reader = writer.reader();
+    thread::scope(|s| {
+        for _ in 0..4 {
+            let reader = reader.clone();
+            s.spawn(move || {
+                let mut next = 0;
+                while next < 300 {
+                    next = reader.next_index().unwrap();
+                    for idx in [next.saturating_sub(1), next / 2] {
+                        if let Some(e) = reader.get(idx).unwrap() {
+                            assert_eq!(&*e, &*event(idx));
+                        }
+                    }
+                    // events appended in the meantime are included
+                    let start = next.saturating_sub(15);
+                    let events = reader.events(start..).unwrap().map(|e| e.unwrap()).collect::<Vec<_>>();
+                    assert!(events.len() >= (next - start) as usize);
+                    for (idx, e) in (start..).zip(events) {
+                        assert_eq!((e.idx(), &*e), (idx, &*event(idx)));
+                    }
+                }
+            });
+        }
+        for i in 0..300 {
+            writer.append(&event(i)).unwrap();
+        }
+    });
+}
+
+#[cfg(feature = "fbr")]
+#[test]
+fn fbr_cache() {
+    use eventfile::SendFbrCache;
+    use fbr_cache::FbrCache;
+
+    let dir = tempdir().unwrap();
+    let cache = SendFbrCache::new(FbrCache::new(100));
+    let config = EventFileConfig::new(0).block_event_limit(10).cache(Box::new(cache));
+    let mut writer = EventFile::new(1, dir.path().join("file"), config).unwrap();
+    for i in 0..50u8 {
+        writer.append(&[i]).unwrap();
+    }
+
+    // the writer and its cache move to another thread, reads there and here share the cache
+    let reader = writer.reader();
+    let writer = thread::spawn(move || {
+        for _ in 0..2 {
+            let events = writer.events(..).unwrap().map(|e| e.unwrap()[0]).collect::<Vec<_>>();
+            assert_eq!(events, (0..50).collect::<Vec<_>>());
+        }
+        writer
+    })
+    .join()
+    .unwrap();
+    assert_eq!(&*reader.get(17).unwrap().unwrap(), &[17]);
+    drop(writer);
+}
@@ -0,0 +1,104 @@
+use ed25519_dalek::{Keypair, PublicKey, SecretKey};
+use eventfile::{Error, EventFile, EventFileConfig};
+use std::fs;
+use tempfile::tempdir;
+
+fn keypair(seed: u8) -> Keypair {
+    let secret = SecretKey::from_bytes(&[seed; 32]).unwrap();
+    let public = PublicKey::from(&secret);
+    Keypair { secret, public }
+}
+
+/// Length and checksum of the first block as shown by the dump.
+fn first_block(dump: &str) -> (usize, u32) {
+    let line = dump.lines().find(|l| l.starts_with("block @ 0:")).unwrap();
+    let field = |name: &str| line.split(' ').find_map(|f| f.strip_prefix(name)).unwrap();
+    let length = field("length=").parse().unwrap();
+    let checksum = u32::from_str_radix(field("checksum=").trim_start_matches("0x"), 16).unwrap();
+    (length, checksum)
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> usize {
+    haystack.windows(needle.len()).position(|w| w == needle).unwrap()
+}
+
+/// Encode a number in the byte order of the file format.
+fn encode(n: u32) -> [u8; 4] {
+    if cfg!(feature = "native") {
+        n.to_ne_bytes()
+    } else {
+        n.to_be_bytes()
+    }
+}
+
+#[test]
+fn signed() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("file");
+    let config = || EventFileConfig::new(0).block_event_limit(4).signing_key(keypair(1));
+    let public = keypair(1).public;
+
+    let mut file = EventFile::new(1, path.clone(), config()).unwrap();
+    for i in 0..20u8 {
+        file.append(&[i; 10]).unwrap();
+    }
+    file.verify(&public).unwrap();
+    assert!(matches!(file.verify(&keypair(2).public), Err(Error::InvalidSignature { .. })));
+
+    // the chain continues after reopening
+    drop(file);
+    let mut file = EventFile::new(1, path.clone(), config()).unwrap();
+    for i in 20..30u8 {
+        file.append(&[i; 10]).unwrap();
+    }
+    file.verify(&public).unwrap();
+
+    // blocks written without a key are reported
+    drop(file);
+    let mut file = EventFile::new(1, path.clone(), EventFileConfig::new(0).block_event_limit(4)).unwrap();
+    for i in 30..33u8 {
+        file.append(&[i; 10]).unwrap();
+    }
+    let err = file.verify(&public).unwrap_err();
+    assert!(
+        matches!(err, Error::InvalidSignature { message: "leaf is not signed", .. }),
+        "{}",
+        err
+    );
+}
+
+#[test]
+fn tampered() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("file");
+    let config = || EventFileConfig::new(0).block_event_limit(4).signing_key(keypair(1));
+
+    let mut file = EventFile::new(1, path.clone(), config()).unwrap();
+    for i in 0..9u8 {
+        file.append(&[i; 10]).unwrap();
+    }
+    drop(file);
+
+    // rewrite the first leaf including its checksum, as an attacker would
+    let file = EventFile::open_read_only(1, path.clone(), config()).unwrap();
+    let mut dump = Vec::new();
+    file.dump_text(0, &mut dump).unwrap();
+    let dump = String::from_utf8(dump).unwrap();
+    assert!(dump.contains(" signed"));
+    drop(file);
+    let (length, checksum) = first_block(&dump);
+    let mut bytes = fs::read(&path).unwrap();
+    // the payload starts with the leaf header, the block header holds length and checksum in file byte order
+    let payload = 4096 + find(&bytes[4096..], b"LeafHead");
+    assert_eq!(crc32fast::hash(&bytes[payload..payload + length]), checksum);
+    let checksum_at = 4096 + find(&bytes[4096..payload], &encode(checksum));
+    // the leaf’s sealed timestamp follows its magic and start index
+    bytes[payload + 16] ^= 1;
+    let checksum = crc32fast::hash(&bytes[payload..payload + length]);
+    bytes[checksum_at..checksum_at + 4].copy_from_slice(&encode(checksum));
+    fs::write(&path, bytes).unwrap();
+
+    let file = EventFile::open_read_only(1, path.clone(), config()).unwrap();
+    let err = file.verify(&keypair(1).public).unwrap_err();
+    assert!(matches!(err, Error::InvalidSignature { offset: 0, .. }), "{}", err);
+}
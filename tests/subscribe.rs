@@ -0,0 +1,47 @@
+use eventfile::{EventFile, EventFileConfig};
+use std::{thread, time::Duration};
+use tempfile::tempdir;
+
+#[test]
+fn in_process() {
+    let dir = tempdir().unwrap();
+    let config = || EventFileConfig::new(0).block_event_limit(10);
+    let mut file = EventFile::new(1, dir.path().join("file"), config()).unwrap();
+    for i in 0..12u8 {
+        file.append(&[i]).unwrap();
+    }
+
+    let sub = file.subscribe(5).unwrap();
+    let consumer = thread::spawn(move || sub.map(|ev| ev.unwrap()[0]).collect::<Vec<_>>());
+    for i in 12..30u8 {
+        file.append(&[i]).unwrap();
+    }
+    file.append_batch([&[30u8][..], &[31]]).unwrap();
+    drop(file);
+
+    assert_eq!(consumer.join().unwrap(), (5..32).collect::<Vec<_>>());
+}
+
+#[test]
+fn polling() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("file");
+    let config = || EventFileConfig::new(0).block_event_limit(10);
+    let mut file = EventFile::new(1, path.clone(), config()).unwrap();
+    file.append(b"a").unwrap();
+
+    let reader = EventFile::open_read_only(2, path, config()).unwrap();
+    let mut sub = reader.subscribe(0, Duration::from_millis(5));
+    let timeout = Duration::from_millis(100);
+    assert_eq!(&*sub.next_timeout(timeout).unwrap().unwrap(), b"a");
+    assert!(sub.next_timeout(Duration::from_millis(20)).unwrap().is_none());
+
+    for _ in 0..15 {
+        file.append(b"b").unwrap();
+    }
+    for idx in 1..16 {
+        let ev = sub.next_timeout(timeout).unwrap().unwrap();
+        assert_eq!((ev.idx(), &*ev), (idx, &b"b"[..]));
+    }
+    assert_eq!(sub.next_index(), 16);
+}
@@ -0,0 +1,53 @@
+use eventfile::{EventFile, EventFileConfig};
+use tempfile::tempdir;
+
+fn indices(file: &EventFile, range: impl std::ops::RangeBounds<u64>) -> Vec<u64> {
+    let ev = file.iter_time(range).unwrap().map(|ev| u64::from_be_bytes((&*ev.unwrap()).try_into().unwrap()));
+    ev.collect()
+}
+
+#[test]
+fn seek_time() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("file");
+    let config = || EventFileConfig::new(0).block_event_limit(4);
+    let mut file = EventFile::new(1, path.clone(), config()).unwrap();
+    for i in 0..40u64 {
+        file.append_with_time(&i.to_be_bytes(), 100 + i * 10).unwrap();
+    }
+
+    assert_eq!(file.seek_time(0).unwrap(), 0);
+    assert_eq!(file.seek_time(100).unwrap(), 0);
+    assert_eq!(file.seek_time(101).unwrap(), 1);
+    assert_eq!(file.seek_time(250).unwrap(), 15);
+    assert_eq!(file.seek_time(255).unwrap(), 16);
+    // last events are still in the staging area
+    assert_eq!(file.seek_time(490).unwrap(), 39);
+    assert_eq!(file.seek_time(491).unwrap(), 40);
+
+    assert_eq!(indices(&file, 200..230), vec![10, 11, 12]);
+    assert_eq!(indices(&file, 200..=230), vec![10, 11, 12, 13]);
+    assert_eq!(indices(&file, 475..), vec![38, 39]);
+    assert_eq!(indices(&file, ..120), vec![0, 1]);
+    assert!(indices(&file, 1000..).is_empty());
+
+    let reader = EventFile::open_read_only(2, path, config()).unwrap();
+    assert_eq!(reader.seek_time(250).unwrap(), 15);
+
+    // events without timestamps are found by searching for zero
+    file.append(b"none").unwrap();
+    assert_eq!(file.seek_time(0).unwrap(), 0);
+    assert_eq!(file.seek_time(491).unwrap(), 41);
+
+    // batched events keep their timestamps, in the staging area and once compressed
+    let batch = (41..44u64).map(|i| (i.to_be_bytes(), 1000 + i)).collect::<Vec<_>>();
+    let range = file.append_batch_with_time(batch.iter().map(|(e, t)| (&e[..], *t))).unwrap();
+    assert_eq!(range, 41..44);
+    assert_eq!(file.seek_time(1042).unwrap(), 42);
+    assert_eq!(indices(&file, 1042..), vec![42, 43]);
+    for i in 44..60u64 {
+        file.append_with_time(&i.to_be_bytes(), 2000 + i).unwrap();
+    }
+    assert_eq!(file.seek_time(1000).unwrap(), 41);
+    assert_eq!(file.seek_time(1043).unwrap(), 43);
+}
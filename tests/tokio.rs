@@ -0,0 +1,73 @@
+use eventfile::{AsyncEventFile, EventFile, EventFileConfig, EventStream};
+use futures_core::Stream;
+use std::{future::poll_fn, pin::Pin};
+use tempfile::tempdir;
+
+async fn next(stream: &mut EventStream) -> Option<(u64, u8)> {
+    let ev = poll_fn(|cx| Pin::new(&mut *stream).poll_next(cx)).await?.unwrap();
+    Some((ev.idx(), ev[0]))
+}
+
+#[test]
+fn streams() {
+    let dir = tempdir().unwrap();
+    let file = EventFile::new(1, dir.path().join("file"), EventFileConfig::new(0).block_event_limit(10)).unwrap();
+    let rt = tokio::runtime::Builder::new_multi_thread().build().unwrap();
+
+    rt.block_on(async move {
+        let file = AsyncEventFile::new(file);
+        for i in 0..25u8 {
+            assert_eq!(file.append(vec![i]).await.unwrap(), u64::from(i));
+        }
+        assert_eq!(&*file.get(12).await.unwrap().unwrap(), &[12]);
+        assert!(file.append_if(3, [0]).await.is_err());
+
+        let mut range = file.events(8..12);
+        for i in 8..12u8 {
+            assert_eq!(next(&mut range).await, Some((u64::from(i), i)));
+        }
+        assert_eq!(next(&mut range).await, None);
+
+        let mut all = file.events(..);
+        let mut count = 0;
+        while next(&mut all).await.is_some() {
+            count += 1;
+        }
+        assert_eq!(count, 25);
+
+        let mut tail = file.subscribe(20);
+        for i in 20..25u8 {
+            assert_eq!(next(&mut tail).await, Some((u64::from(i), i)));
+        }
+        let writer = file.clone();
+        tokio::spawn(async move {
+            for i in 25..40u8 {
+                writer.append([i]).await.unwrap();
+            }
+        })
+        .await
+        .unwrap();
+        for i in 25..40u8 {
+            assert_eq!(next(&mut tail).await, Some((u64::from(i), i)));
+        }
+        drop(file);
+        assert_eq!(next(&mut tail).await, None);
+    });
+}
+
+#[test]
+fn live_tails() {
+    let dir = tempdir().unwrap();
+    let file = EventFile::new(1, dir.path().join("file"), EventFileConfig::new(0)).unwrap();
+    // waiting tails must not take up the blocking thread, which appending needs
+    let rt = tokio::runtime::Builder::new_multi_thread().max_blocking_threads(1).build().unwrap();
+
+    rt.block_on(async move {
+        let file = AsyncEventFile::new(file);
+        let mut tails = (0..4).map(|_| file.subscribe(0)).collect::<Vec<_>>();
+        file.append([7]).await.unwrap();
+        for tail in &mut tails {
+            assert_eq!(next(tail).await, Some((0, 7)));
+        }
+    });
+}
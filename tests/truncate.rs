@@ -0,0 +1,103 @@
+use eventfile::{Error, EventFile, EventFileConfig, Retention};
+use std::{thread, time::Duration};
+use tempfile::tempdir;
+
+fn event(i: u64) -> Vec<u8> {
+    i.to_be_bytes().to_vec()
+}
+
+fn all(file: &EventFile) -> Vec<u64> {
+    let ev = file.events(..).unwrap().map(|ev| u64::from_be_bytes((&*ev.unwrap()).try_into().unwrap()));
+    ev.collect()
+}
+
+#[test]
+fn truncate() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("file");
+    let config = || EventFileConfig::new(0).block_event_limit(4);
+    let mut file = EventFile::new(1, path.clone(), config()).unwrap();
+    for i in 0..60 {
+        file.append(&event(i)).unwrap();
+    }
+
+    // leaves hold three events each
+    file.truncate_before(20).unwrap();
+    assert_eq!(file.first_index().unwrap(), 18);
+    assert_eq!(file.len().unwrap(), 42);
+    assert_eq!(file.reader().len().unwrap(), 42);
+    assert!(matches!(file.get(17), Err(Error::DataNotPresent { .. })));
+    assert!(file.events(10..).is_err());
+    assert_eq!(&*file.get(18).unwrap().unwrap(), &event(18));
+    assert_eq!(all(&file), (18..60).collect::<Vec<_>>());
+    let rev = file.iter_rev(..).unwrap().map(|leaf| leaf.unwrap().start_idx()).last();
+    assert_eq!(rev, Some(18));
+
+    // truncating further back does nothing
+    file.truncate_before(5).unwrap();
+    assert_eq!(file.first_index().unwrap(), 18);
+
+    drop(file);
+    let mut file = EventFile::new(1, path.clone(), config()).unwrap();
+    assert_eq!(file.first_index().unwrap(), 18);
+    for i in 60..120 {
+        file.append(&event(i)).unwrap();
+    }
+    assert_eq!(all(&file), (18..120).collect::<Vec<_>>());
+
+    // dropping all blocks leaves the staging area
+    file.append(&event(120)).unwrap();
+    let mut reader = EventFile::open_read_only(2, path, config()).unwrap();
+    file.truncate_before(1000).unwrap();
+    assert_eq!(file.first_index().unwrap(), 120);
+    assert_eq!(all(&file), vec![120]);
+    reader.refresh().unwrap();
+    assert_eq!(reader.first_index().unwrap(), 120);
+}
+
+#[test]
+fn retention() {
+    let dir = tempdir().unwrap();
+    let config = |name: &str, retention| {
+        let config = EventFileConfig::new(0).block_event_limit(4).retention(retention);
+        EventFile::new(1, dir.path().join(name), config).unwrap()
+    };
+
+    let mut file = config("events", Retention::Events(7));
+    for i in 0..31 {
+        file.append(&event(i)).unwrap();
+    }
+    assert_eq!(file.first_index().unwrap(), 21);
+    assert_eq!(all(&file), (21..31).collect::<Vec<_>>());
+
+    let mut file = config("bytes", Retention::Bytes(500));
+    let big = vec![0u8; 100];
+    for _ in 0..30 {
+        file.append(&big).unwrap();
+    }
+    let first = file.first_index().unwrap();
+    assert!(first > 0 && first % 3 == 0, "{}", first);
+    let mut s = Vec::new();
+    file.dump_text(0, &mut s).unwrap();
+    let s = String::from_utf8(s).unwrap();
+    let header = s.lines().next().unwrap();
+    let offset = |key: &str| {
+        let v = header.split(' ').find_map(|kv| kv.strip_prefix(key)).unwrap();
+        v.parse::<u64>().unwrap()
+    };
+    assert!(offset("end=") - offset("start=") <= 500, "{}", header);
+
+    let mut file = config("age", Retention::Age(Duration::from_secs(3600)));
+    for i in 0..9 {
+        file.append(&event(i)).unwrap();
+    }
+    assert_eq!(file.first_index().unwrap(), 0);
+
+    // only the newest block may have been written within the same millisecond
+    let mut file = config("age0", Retention::Age(Duration::ZERO));
+    for i in 0..9 {
+        file.append(&event(i)).unwrap();
+        thread::sleep(Duration::from_millis(2));
+    }
+    assert!(file.first_index().unwrap() >= 6);
+}